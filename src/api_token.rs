@@ -0,0 +1,113 @@
+use crate::paste::random_paste_id;
+use crate::state_management::TYRState;
+use chrono::{DateTime, Local};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, State};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// The length of a generated api token.
+pub static API_TOKEN_LENGTH: usize = 40;
+
+/// The maximum number of api tokens a single login can have at once.
+pub static API_TOKENS_PER_LOGIN_CAP: usize = 10;
+
+/// The maximum length of the label a user gives an api token.
+pub static API_TOKEN_LABEL_LENGTH_CAP: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// An api token generated by a logged in user, the token itself is never stored, only its hash.
+pub struct ApiToken {
+    pub login: String, // the login hash of the user the token acts as
+    pub label: String,
+    pub created_at: DateTime<Local>,
+    pub last_used: Option<DateTime<Local>>,
+}
+
+/// Returns the hash an api token is stored under.
+pub fn hash_api_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Generates a new api token for the login, returning the token, or an error if the login has too many tokens.
+/// The token is only ever shown to the user once, as only its hash is kept.
+pub fn create_api_token(
+    tokens: &mut HashMap<String, ApiToken>,
+    login: &str,
+    label: &str,
+) -> Result<String, String> {
+    let token_count = tokens.values().filter(|token| token.login == login).count();
+    if token_count >= API_TOKENS_PER_LOGIN_CAP {
+        return Err(format!(
+            "You can only have {API_TOKENS_PER_LOGIN_CAP} api tokens, revoke one to create another."
+        ));
+    }
+
+    let token = random_paste_id(API_TOKEN_LENGTH);
+    let mut label = label.trim().to_string();
+    label.truncate(API_TOKEN_LABEL_LENGTH_CAP);
+    tokens.insert(
+        hash_api_token(&token),
+        ApiToken {
+            login: login.to_string(),
+            label,
+            created_at: Local::now(),
+            last_used: None,
+        },
+    );
+    Ok(token)
+}
+
+/// Request guard for the api, reading a bearer token from the Authorization header.
+/// Holds the login hash the token acts as, or none if no token was given.
+/// Requests with a token that is invalid or revoked are rejected, instead of being treated as anonymous.
+pub struct ApiAuth(pub Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiAuth {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = match req.headers().get_one("Authorization") {
+            None => return Outcome::Success(Self(None)),
+            Some(header) => match header.strip_prefix("Bearer ") {
+                None => return Outcome::Error((Status::Unauthorized, ())),
+                Some(token) => token.trim(),
+            },
+        };
+
+        let state: &State<TYRState> = req.guard::<&State<TYRState>>().await.unwrap();
+        let mut tokens = state.api_tokens.write().unwrap();
+        match tokens.get_mut(&hash_api_token(token)) {
+            None => Outcome::Error((Status::Unauthorized, ())),
+            Some(api_token) => {
+                api_token.last_used = Some(Local::now());
+                Outcome::Success(Self(Some(api_token.login.clone())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api_token::{create_api_token, hash_api_token, API_TOKENS_PER_LOGIN_CAP};
+    use std::collections::HashMap;
+
+    #[test]
+    fn api_tokens_are_hashed_and_capped() {
+        let mut tokens = HashMap::new();
+        let token = create_api_token(&mut tokens, "abc", " laptop ").unwrap();
+        let stored = tokens.get(&hash_api_token(&token)).unwrap();
+        assert_eq!(stored.login, "abc");
+        assert_eq!(stored.label, "laptop");
+        assert!(!tokens.contains_key(&token));
+
+        for _ in 1..API_TOKENS_PER_LOGIN_CAP {
+            create_api_token(&mut tokens, "abc", "").unwrap();
+        }
+        assert!(create_api_token(&mut tokens, "abc", "").is_err());
+        assert!(create_api_token(&mut tokens, "abd", "").is_ok());
+    }
+}
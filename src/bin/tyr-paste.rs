@@ -0,0 +1,583 @@
+//! Command line client for the paste api of thank_you_rocket_rs.
+//! Reads pastes from stdin or files, and prints the url of each created paste.
+//! The server url and api token are stored in a config file, see `tyr-paste help`.
+
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::io::{Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+use std::process::exit;
+
+static USAGE: &str = "Usage:
+    tyr-paste [options] [files...]       create a paste from each file, or from stdin if no files are given
+    tyr-paste get <id or url>            print the contents of a paste
+    tyr-paste delete <id or url>         delete one of your pastes
+    tyr-paste list                       list the pastes created with your api token
+    tyr-paste config [--server <url>] [--token <token>]
+                                         save the server url and api token to the config file
+
+Paste options:
+    -e, --expiry <10m|1d|1w|never>       how long the paste lasts
+    -v, --visibility <public|unlisted|private|shared>
+                                         who can view the paste, shared pastes can be viewed by the --allow logins
+    -a, --allow <login id>               a login id allowed to view a shared paste, can be repeated
+    -l, --language <language>            language used for highlighting, taken from the file extension if not given
+    -u, --custom-url <url>               custom url for the paste, only for verified users
+    -t, --title <title>                  title shown above the paste
+    -d, --description <description>      description shown above the paste
+    -g, --tag <tag>                      a tag the paste can be browsed by, can be repeated
+    --list                               list the paste in the public paste directory, public pastes only
+    -m, --max-views <n>                  delete the paste after this many views
+    -b, --burn                           delete the paste after it is read once
+    -p, --password <password>            password needed to view the paste, also used by get
+
+Other options:
+    --server <url>                       server to use instead of the one in the config file
+    --token <token>                      api token to use instead of the one in the config file
+    --deletion-token <token>             deletion token given when the paste was created, lets delete work without the api token of the poster
+
+Api tokens are generated at <server>/paste/api_tokens.
+The config file is $TYR_PASTE_CONFIG, or tyr-paste/config.json in $XDG_CONFIG_HOME or ~/.config.";
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+/// The settings saved between runs.
+struct Config {
+    server: Option<String>,
+    token: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+/// The options for creating pastes.
+struct PasteArgs {
+    expiry: Option<String>,
+    visibility: Option<String>,
+    allowed_logins: Vec<String>,
+    language: Option<String>,
+    custom_url: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    tags: Vec<String>,
+    max_views: Option<u32>,
+    burn_after_reading: bool,
+    list_publicly: bool,
+    files: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Create(Box<PasteArgs>),
+    Get(String),
+    Delete(String),
+    List,
+    Config,
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The parsed command line.
+struct Args {
+    command: Command,
+    server: Option<String>,
+    token: Option<String>,
+    deletion_token: Option<String>,
+    password: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+/// The fields of the paste metadata returned by the api that are printed.
+struct ApiPaste {
+    id: String,
+    view_path: String,
+    post_time: String,
+    visibility: String,
+    view_count: u32,
+    expires_at: Option<String>,
+    deletion_token: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiError {
+    error: String,
+}
+
+/// Parses the command line arguments, not including the program name.
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut paste_args = PasteArgs::default();
+    let mut server = None;
+    let mut token = None;
+    let mut deletion_token = None;
+    let mut password = None;
+    let mut positional = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{arg} needs a value."))
+        };
+        match arg.as_str() {
+            "-e" | "--expiry" => paste_args.expiry = Some(expiry_name(&value()?)?),
+            "-v" | "--visibility" => paste_args.visibility = Some(visibility_name(&value()?)?),
+            "-a" | "--allow" => paste_args.allowed_logins.push(value()?),
+            "-l" | "--language" => paste_args.language = Some(value()?),
+            "-u" | "--custom-url" => paste_args.custom_url = Some(value()?),
+            "-t" | "--title" => paste_args.title = Some(value()?),
+            "-d" | "--description" => paste_args.description = Some(value()?),
+            "-g" | "--tag" => paste_args.tags.push(value()?),
+            "-m" | "--max-views" => {
+                let max_views = value()?;
+                paste_args.max_views = Some(
+                    max_views
+                        .parse()
+                        .map_err(|_| format!("{max_views} is not a number of views."))?,
+                )
+            }
+            "-b" | "--burn" => paste_args.burn_after_reading = true,
+            "--list" => paste_args.list_publicly = true,
+            "-p" | "--password" => password = Some(value()?),
+            "--server" => server = Some(value()?),
+            "--token" => token = Some(value()?),
+            "--deletion-token" => deletion_token = Some(value()?),
+            "-h" | "--help" => positional.insert(0, "help".to_string()),
+            "-" => positional.push(arg.clone()),
+            flag if flag.starts_with('-') => return Err(format!("Unknown option {flag}.")),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let command = match positional.first().map(|arg| arg.as_str()) {
+        Some("help") => Command::Help,
+        Some("get") => Command::Get(single_argument(&positional, "get")?),
+        Some("delete") => Command::Delete(single_argument(&positional, "delete")?),
+        Some("list") => Command::List,
+        Some("config") => Command::Config,
+        _ => {
+            paste_args.files = positional;
+            Command::Create(Box::new(paste_args))
+        }
+    };
+    Ok(Args {
+        command,
+        server,
+        token,
+        deletion_token,
+        password,
+    })
+}
+
+/// Returns the single argument of a command, e.g. the paste id of get.
+fn single_argument(positional: &[String], command: &str) -> Result<String, String> {
+    match positional {
+        [_, argument] => Ok(paste_id_from(argument)),
+        _ => Err(format!("{command} takes a single paste id or url.")),
+    }
+}
+
+/// Returns the paste id of a paste url, or the argument itself if it is already an id.
+fn paste_id_from(argument: &str) -> String {
+    argument
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or(argument)
+        .to_string()
+}
+
+/// Converts the short expiry names to the names the api accepts.
+fn expiry_name(expiry: &str) -> Result<String, String> {
+    match expiry.to_lowercase().as_str() {
+        "10m" | "tenminutes" => Ok("TenMinutes".to_string()),
+        "1d" | "oneday" => Ok("OneDay".to_string()),
+        "1w" | "oneweek" => Ok("OneWeek".to_string()),
+        "never" => Ok("Never".to_string()),
+        _ => Err(format!(
+            "{expiry} is not an expiry, use one of 10m, 1d, 1w or never."
+        )),
+    }
+}
+
+/// Converts the short visibility names to the names the api accepts.
+fn visibility_name(visibility: &str) -> Result<String, String> {
+    match visibility.to_lowercase().as_str() {
+        "public" => Ok("Public".to_string()),
+        "unlisted" => Ok("Unlisted".to_string()),
+        "private" | "privatetologin" => Ok("PrivateToLogin".to_string()),
+        "shared" | "privatetologins" => Ok("PrivateToLogins".to_string()),
+        _ => Err(format!(
+            "{visibility} is not a visibility, use one of public, unlisted, private or shared."
+        )),
+    }
+}
+
+/// Returns the path of the config file.
+fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("TYR_PASTE_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("tyr-paste").join("config.json"))
+}
+
+/// Reads the config file, a missing or malformed file is treated as empty.
+fn load_config() -> Config {
+    config_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|config| serde_json::from_str(&config).ok())
+        .unwrap_or_default()
+}
+
+fn save_config(config: &Config) -> Result<PathBuf, String> {
+    let path = config_path().ok_or("Unable to find a config directory, set $TYR_PASTE_CONFIG.")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| format!("Unable to create config dir. {err}"))?;
+    }
+    let config = serde_json::to_string_pretty(config).unwrap();
+    // the config holds the api token, so it is only readable by the user.
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(&path)
+        .map_err(|err| format!("Unable to write config file. {err}"))?;
+    // a config file saved by an older version keeps its permissions when opened, so they are set again.
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))
+        .map_err(|err| format!("Unable to set the permissions of the config file. {err}"))?;
+    file.write_all(config.as_bytes())
+        .map_err(|err| format!("Unable to write config file. {err}"))?;
+    Ok(path)
+}
+
+/// Sends the request, turning error responses of the api into their error message.
+fn send(request: RequestBuilder) -> Result<Response, String> {
+    let response = request
+        .send()
+        .map_err(|err| format!("Unable to reach the server. {err}"))?;
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let error = response
+        .json::<ApiError>()
+        .map(|error| error.error)
+        .unwrap_or_else(|_| status.to_string());
+    Err(format!("{} {}", status.as_u16(), error))
+}
+
+/// Adds the api token to the request, if there is one.
+fn authorized(request: RequestBuilder, token: Option<&str>) -> RequestBuilder {
+    match token {
+        None => request,
+        Some(token) => request.bearer_auth(token),
+    }
+}
+
+/// Creates a paste from each file, or from stdin, printing the url of each paste.
+fn create_pastes(
+    client: &Client,
+    server: &str,
+    token: Option<&str>,
+    paste_args: &PasteArgs,
+    password: Option<&str>,
+) -> Result<(), String> {
+    let token = token.ok_or(
+        "Creating pastes needs an api token, set one with tyr-paste config --token <token>.",
+    )?;
+    let files = match paste_args.files.is_empty() {
+        true => vec!["-".to_string()],
+        false => paste_args.files.clone(),
+    };
+
+    for file in &files {
+        let content = match file.as_str() {
+            "-" => {
+                let mut content = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut content)
+                    .map_err(|err| format!("Unable to read stdin. {err}"))?;
+                content
+            }
+            file => {
+                fs::read_to_string(file).map_err(|err| format!("Unable to read {file}. {err}"))?
+            }
+        };
+        let language = paste_args.language.clone().or_else(|| {
+            PathBuf::from(file)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .map(|extension| extension.to_string())
+        });
+
+        let body = json!({
+            "content": content,
+            "custom_url": paste_args.custom_url,
+            "expiry": paste_args.expiry,
+            "max_views": paste_args.max_views,
+            "burn_after_reading": paste_args.burn_after_reading,
+            "language": language,
+            "visibility": paste_args.visibility,
+            "allowed_logins": paste_args.allowed_logins,
+            "password": password,
+            "title": paste_args.title,
+            "description": paste_args.description,
+            "tags": paste_args.tags,
+            "list_publicly": paste_args.list_publicly,
+        });
+        let paste = send(
+            client
+                .post(format!("{server}/api/v1/pastes"))
+                .bearer_auth(token)
+                .json(&body),
+        )?
+        .json::<ApiPaste>()
+        .map_err(|err| format!("Unable to read the response of the server. {err}"))?;
+
+        println!("{server}{}", paste.view_path);
+        if let Some(deletion_token) = paste.deletion_token {
+            eprintln!("Deletion token for {}: {deletion_token}", paste.id);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the raw contents of a paste to stdout.
+fn get_paste(
+    client: &Client,
+    server: &str,
+    token: Option<&str>,
+    paste_id: &str,
+    password: Option<&str>,
+) -> Result<(), String> {
+    let mut request = authorized(
+        client.get(format!("{server}/api/v1/pastes/{paste_id}/raw")),
+        token,
+    );
+    if let Some(password) = password {
+        request = request.query(&[("password", password)]);
+    }
+    let bytes = send(request)?
+        .bytes()
+        .map_err(|err| format!("Unable to read the paste. {err}"))?;
+    std::io::stdout()
+        .write_all(&bytes)
+        .map_err(|err| format!("Unable to write the paste. {err}"))
+}
+
+/// Builds the request deleting a paste, with the deletion token of the paste if one is given, or else the api token of the poster.
+/// The deletion token is only sent in the query, as the server rejects anything but an api token as bearer auth.
+fn delete_request(
+    client: &Client,
+    server: &str,
+    token: Option<&str>,
+    deletion_token: Option<&str>,
+    paste_id: &str,
+) -> RequestBuilder {
+    let request = client.delete(format!("{server}/api/v1/pastes/{paste_id}"));
+    match deletion_token {
+        Some(deletion_token) => request.query(&[("token", deletion_token)]),
+        None => authorized(request, token),
+    }
+}
+
+/// Deletes a paste, either as its poster or with its deletion token.
+fn delete_paste(
+    client: &Client,
+    server: &str,
+    token: Option<&str>,
+    deletion_token: Option<&str>,
+    paste_id: &str,
+) -> Result<(), String> {
+    if token.is_none() && deletion_token.is_none() {
+        return Err(
+            "Deleting a paste needs an api token or its deletion token, given with --deletion-token <token>."
+                .to_string(),
+        );
+    }
+    send(delete_request(
+        client,
+        server,
+        token,
+        deletion_token,
+        paste_id,
+    ))?;
+    println!("Deleted {paste_id}");
+    Ok(())
+}
+
+/// Prints the pastes created with the api token, newest first.
+fn list_pastes(client: &Client, server: &str, token: Option<&str>) -> Result<(), String> {
+    let token = token.ok_or(
+        "Listing pastes needs an api token, set one with tyr-paste config --token <token>.",
+    )?;
+    let pastes = send(
+        client
+            .get(format!("{server}/api/v1/pastes"))
+            .bearer_auth(token),
+    )?
+    .json::<Vec<ApiPaste>>()
+    .map_err(|err| format!("Unable to read the response of the server. {err}"))?;
+
+    for paste in pastes {
+        println!(
+            "{server}{}  posted {}  {}  {} views  expires {}",
+            paste.view_path,
+            paste.post_time,
+            paste.visibility,
+            paste.view_count,
+            paste.expires_at.as_deref().unwrap_or("never")
+        );
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let config = load_config();
+    if args.command == Command::Help {
+        println!("{USAGE}");
+        return Ok(());
+    }
+    if args.command == Command::Config {
+        let config = Config {
+            server: args.server.or(config.server),
+            token: args.token.or(config.token),
+        };
+        let path = save_config(&config)?;
+        println!("Saved config to {}", path.display());
+        return Ok(());
+    }
+
+    let server = args
+        .server
+        .or(config.server)
+        .ok_or("No server set, set one with tyr-paste config --server <url>.")?;
+    let server = server.trim_end_matches('/');
+    let token = args.token.or(config.token);
+    let password = args.password.as_deref();
+    let client = Client::new();
+
+    match &args.command {
+        Command::Create(paste_args) => {
+            create_pastes(&client, server, token.as_deref(), paste_args, password)
+        }
+        Command::Get(paste_id) => get_paste(&client, server, token.as_deref(), paste_id, password),
+        Command::Delete(paste_id) => delete_paste(
+            &client,
+            server,
+            token.as_deref(),
+            args.deletion_token.as_deref(),
+            paste_id,
+        ),
+        Command::List => list_pastes(&client, server, token.as_deref()),
+        Command::Config | Command::Help => Ok(()),
+    }
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let result = parse_args(&args).and_then(run);
+    if let Err(err) = result {
+        eprintln!("{err}");
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{delete_request, parse_args, save_config, Command, Config, PasteArgs};
+    use reqwest::blocking::Client;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn argument_parsing() {
+        let parsed = parse_args(&args(&[
+            "-e", "1d", "-v", "shared", "-a", "abc", "--burn", "log.txt", "-",
+        ]))
+        .unwrap();
+        assert_eq!(
+            parsed.command,
+            Command::Create(Box::new(PasteArgs {
+                expiry: Some("OneDay".to_string()),
+                visibility: Some("PrivateToLogins".to_string()),
+                allowed_logins: vec!["abc".to_string()],
+                burn_after_reading: true,
+                files: args(&["log.txt", "-"]),
+                ..Default::default()
+            }))
+        );
+
+        let parsed = parse_args(&args(&[
+            "get",
+            "http://localhost:8080/paste/view/abcd1234",
+            "--password",
+            "hunter2",
+        ]))
+        .unwrap();
+        assert_eq!(parsed.command, Command::Get("abcd1234".to_string()));
+        assert_eq!(parsed.password, Some("hunter2".to_string()));
+
+        assert!(parse_args(&args(&["-e", "forever"])).is_err());
+        assert!(parse_args(&args(&["--expiry"])).is_err());
+        assert!(parse_args(&args(&["delete"])).is_err());
+    }
+
+    #[test]
+    fn deletion_tokens_are_only_sent_in_the_query() {
+        let client = Client::new();
+        let request = delete_request(
+            &client,
+            "http://localhost:8080",
+            Some("api-token"),
+            Some("deletion-token"),
+            "abcd1234",
+        )
+        .build()
+        .unwrap();
+        assert_eq!(
+            request.url().as_str(),
+            "http://localhost:8080/api/v1/pastes/abcd1234?token=deletion-token"
+        );
+        assert!(request.headers().get("Authorization").is_none());
+
+        let request = delete_request(
+            &client,
+            "http://localhost:8080",
+            Some("api-token"),
+            None,
+            "abcd1234",
+        )
+        .build()
+        .unwrap();
+        assert_eq!(request.url().query(), None);
+        assert_eq!(request.headers()["Authorization"], "Bearer api-token");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn config_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("tyr-paste-test-{}.json", std::process::id()));
+        std::fs::write(&path, "{}").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        std::env::set_var("TYR_PASTE_CONFIG", &path);
+        save_config(&Config {
+            server: None,
+            token: Some("api-token".to_string()),
+        })
+        .unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,324 @@
+use crate::message::Message;
+use crate::state_management::TYRState;
+use crate::user::User;
+use crate::{CHAT_MESSAGE_LENGTH_CAP, MESSAGE_LENGTH_CAP};
+use chrono::{DateTime, Local};
+use rocket::tokio::sync::{broadcast, mpsc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::UNIX_EPOCH;
+
+/// The number of chat events buffered for each admin console before the slowest console starts missing events.
+pub static CHAT_EVENT_CAPACITY: usize = 64;
+
+/// The most chat sessions a single ip can have open at once.
+pub static CHAT_SESSIONS_PER_IP_CAP: usize = 2;
+
+/// The most chat sessions that can be open at once across every visitor.
+pub static CHAT_SESSIONS_CAP: usize = 50;
+
+/// The most lines kept in a single chat session, further lines are ignored.
+pub static CHAT_TRANSCRIPT_LINES_CAP: usize = 200;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatSender {
+    Visitor,
+    Host,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+/// A single line said in a chat session.
+pub struct ChatLine {
+    pub from: ChatSender,
+    pub text: String,
+    pub time: DateTime<Local>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// Events sent to every connected admin console.
+pub enum ChatEvent {
+    Opened { session_id: String, ip: String },
+    Line { session_id: String, line: ChatLine },
+    Closed { session_id: String },
+}
+
+#[derive(Deserialize, Debug, Clone)]
+/// A message sent from an admin console to a visitor.
+pub struct HostMessage {
+    pub session_id: String,
+    pub text: String,
+}
+
+#[derive(Debug)]
+/// A live chat between a visitor and the host, kept until the visitor disconnects.
+pub struct ChatSession {
+    pub ip: String,
+    pub login: Option<String>,
+    pub transcript: Vec<ChatLine>,
+    pub to_visitor: mpsc::UnboundedSender<ChatLine>,
+}
+
+#[derive(Debug)]
+/// All active chat sessions, and the number of admin consoles connected.
+/// The host is considered online while at least one admin console is connected.
+pub struct ChatHub {
+    pub sessions: HashMap<String, ChatSession>,
+    pub admin_connections: usize,
+    pub admin_events: broadcast::Sender<ChatEvent>,
+}
+
+impl Default for ChatHub {
+    fn default() -> Self {
+        Self {
+            sessions: HashMap::new(),
+            admin_connections: 0,
+            admin_events: broadcast::channel(CHAT_EVENT_CAPACITY).0,
+        }
+    }
+}
+
+/// Returns true if the host has an admin console open.
+pub fn is_host_online(state: &TYRState) -> bool {
+    state.chat.read().unwrap().admin_connections > 0
+}
+
+/// Opens a new chat session for a visitor, returning its id and the receiver for lines the visitor should be sent.
+/// Returns None if the visitor is on message cooldown, or if too many sessions are already open.
+pub fn open_session(
+    state: &TYRState,
+    ip: String,
+    login: Option<String>,
+) -> Option<(String, mpsc::UnboundedReceiver<ChatLine>)> {
+    if let Some(user) = state.messages.read().unwrap().get(&ip) {
+        if !user.can_post() {
+            return None;
+        }
+    }
+
+    let mut lock = state.chat.write().unwrap();
+    let ip_sessions = lock
+        .sessions
+        .values()
+        .filter(|session| session.ip == ip)
+        .count();
+    if lock.sessions.len() >= CHAT_SESSIONS_CAP || ip_sessions >= CHAT_SESSIONS_PER_IP_CAP {
+        return None;
+    }
+
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let (to_visitor, receiver) = mpsc::unbounded_channel();
+    let _ = lock.admin_events.send(ChatEvent::Opened {
+        session_id: session_id.clone(),
+        ip: ip.clone(),
+    });
+    lock.sessions.insert(
+        session_id.clone(),
+        ChatSession {
+            ip,
+            login,
+            transcript: vec![],
+            to_visitor,
+        },
+    );
+    Some((session_id, receiver))
+}
+
+/// Adds a line to a session, sending it to both the visitor and every admin console.
+/// Empty lines, lines that are too long, lines past the transcript cap, and lines for sessions that no longer exist are ignored.
+pub fn say(state: &TYRState, session_id: &str, from: ChatSender, text: &str) {
+    let text = text.trim();
+    if text.is_empty() || text.len() > CHAT_MESSAGE_LENGTH_CAP {
+        return;
+    }
+
+    let mut lock = state.chat.write().unwrap();
+    let admin_events = lock.admin_events.clone();
+    if let Some(session) = lock.sessions.get_mut(session_id) {
+        if session.transcript.len() >= CHAT_TRANSCRIPT_LINES_CAP {
+            return;
+        }
+        let line = ChatLine {
+            from,
+            text: text.to_string(),
+            time: Local::now(),
+        };
+        session.transcript.push(line.clone());
+        let _ = session.to_visitor.send(line.clone());
+        let _ = admin_events.send(ChatEvent::Line {
+            session_id: session_id.to_string(),
+            line,
+        });
+    }
+}
+
+/// Closes a session, storing its transcript as a message under the visitors ip and login.
+/// The stored transcript is cut to the message length cap, and puts the visitor on message cooldown like any other message.
+/// Returns true if a transcript was stored, in which case the program state should be saved.
+pub fn close_session(state: &TYRState, session_id: &str) -> bool {
+    let session = {
+        let mut lock = state.chat.write().unwrap();
+        let _ = lock.admin_events.send(ChatEvent::Closed {
+            session_id: session_id.to_string(),
+        });
+        lock.sessions.remove(session_id)
+    };
+
+    let session = match session {
+        Some(session) if !session.transcript.is_empty() => session,
+        _ => return false,
+    };
+
+    let mut text = "Chat transcript:".to_string();
+    for line in &session.transcript {
+        let from = match line.from {
+            ChatSender::Visitor => "visitor",
+            ChatSender::Host => "host",
+        };
+        text.push_str(&format!("\n{from}: {}", line.text));
+    }
+    let mut cut = MESSAGE_LENGTH_CAP.min(text.len());
+    while !text.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    text.truncate(cut);
+
+    let encryption = state.admin_state.read().unwrap().message_encryption.clone();
+    let message = match Message::new(text, session.login, None, encryption.as_ref()) {
+        Ok(message) => message,
+        Err(err) => {
+            println!("Unable to save the transcript of chat session {session_id}. {err}");
+            return false;
+        }
+    };
+
+    {
+        let mut lock = state.messages.write().unwrap();
+        lock.entry(session.ip)
+            .or_insert(User {
+                messages: vec![],
+                last_time_post: UNIX_EPOCH,
+            })
+            .push(message);
+    }
+
+    true
+}
+
+/// Registers a newly connected admin console, returning the events it should be sent to catch up on active sessions,
+/// and the receiver for all future events.
+pub fn connect_admin(state: &TYRState) -> (Vec<ChatEvent>, broadcast::Receiver<ChatEvent>) {
+    let mut lock = state.chat.write().unwrap();
+    lock.admin_connections += 1;
+
+    let mut catch_up = vec![];
+    for (session_id, session) in &lock.sessions {
+        catch_up.push(ChatEvent::Opened {
+            session_id: session_id.clone(),
+            ip: session.ip.clone(),
+        });
+        for line in &session.transcript {
+            catch_up.push(ChatEvent::Line {
+                session_id: session_id.clone(),
+                line: line.clone(),
+            });
+        }
+    }
+
+    (catch_up, lock.admin_events.subscribe())
+}
+
+/// Unregisters an admin console, once none are left the host is no longer online.
+pub fn disconnect_admin(state: &TYRState) {
+    let mut lock = state.chat.write().unwrap();
+    lock.admin_connections = lock.admin_connections.saturating_sub(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chat_session_transcript() {
+        let state = TYRState::default();
+        let (_catch_up, mut admin_events) = connect_admin(&state);
+        assert!(is_host_online(&state));
+
+        let (session_id, mut to_visitor) =
+            open_session(&state, "1.2.3.4".to_string(), None).unwrap();
+        say(&state, &session_id, ChatSender::Visitor, "hello host");
+        say(&state, &session_id, ChatSender::Host, "  hello visitor  ");
+        say(&state, &session_id, ChatSender::Host, "   ");
+
+        assert_eq!(to_visitor.try_recv().unwrap().text, "hello host");
+        assert_eq!(to_visitor.try_recv().unwrap().text, "hello visitor");
+        assert!(to_visitor.try_recv().is_err());
+        assert!(matches!(
+            admin_events.try_recv().unwrap(),
+            ChatEvent::Opened { .. }
+        ));
+
+        let (catch_up, _) = connect_admin(&state);
+        assert_eq!(catch_up.len(), 3);
+
+        assert!(close_session(&state, &session_id));
+
+        let lock = state.messages.read().unwrap();
+        let user = lock.get("1.2.3.4").unwrap();
+        assert_eq!(
+            user.messages[0].text,
+            "Chat transcript:\nvisitor: hello host\nhost: hello visitor"
+        );
+        assert!(!user.can_post());
+        drop(lock);
+
+        // the transcript counts as a message, so the visitor can not chat again while on cooldown.
+        assert!(open_session(&state, "1.2.3.4".to_string(), None).is_none());
+    }
+
+    #[test]
+    fn chat_sessions_and_transcripts_are_capped() {
+        let state = TYRState::default();
+
+        for _ in 0..CHAT_SESSIONS_PER_IP_CAP {
+            assert!(open_session(&state, "1.2.3.4".to_string(), None).is_some());
+        }
+        assert!(open_session(&state, "1.2.3.4".to_string(), None).is_none());
+
+        for visitor in 0..CHAT_SESSIONS_CAP {
+            let _ = open_session(&state, format!("10.0.0.{visitor}"), None);
+        }
+        assert_eq!(state.chat.read().unwrap().sessions.len(), CHAT_SESSIONS_CAP);
+        assert!(open_session(&state, "5.6.7.8".to_string(), None).is_none());
+
+        let session_id = state
+            .chat
+            .read()
+            .unwrap()
+            .sessions
+            .keys()
+            .next()
+            .unwrap()
+            .clone();
+        let ip = state.chat.read().unwrap().sessions[&session_id].ip.clone();
+        for _ in 0..CHAT_TRANSCRIPT_LINES_CAP + 10 {
+            say(
+                &state,
+                &session_id,
+                ChatSender::Visitor,
+                "a line that repeats",
+            );
+        }
+        assert_eq!(
+            state.chat.read().unwrap().sessions[&session_id]
+                .transcript
+                .len(),
+            CHAT_TRANSCRIPT_LINES_CAP
+        );
+
+        assert!(close_session(&state, &session_id));
+        let lock = state.messages.read().unwrap();
+        assert_eq!(lock[&ip].messages[0].text.len(), MESSAGE_LENGTH_CAP);
+    }
+}
@@ -0,0 +1,156 @@
+use maud::{html, Markup, PreEscaped};
+use similar::{ChangeTag, DiffTag, TextDiff};
+
+/// The number of unchanged lines shown around each change in a diff.
+pub static DIFF_CONTEXT_LINES: usize = 3;
+
+#[derive(FromFormField, Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// How a diff between two texts is shown.
+pub enum DiffMode {
+    #[default]
+    Unified,
+    SideBySide,
+}
+
+/// Produces a unified diff of the two texts in the format used by patch and git apply.
+pub fn unified_patch(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(DIFF_CONTEXT_LINES)
+        .header(old_name, new_name)
+        .to_string()
+}
+
+/// Removes the line ending from a line of a diff, the table rows already separate each line.
+fn trim_line(line: &str) -> &str {
+    line.trim_end_matches(['\r', '\n'])
+}
+
+/// Renders a diff of the two texts as a html table, either unified or side by side.
+pub fn render_diff(old: &str, new: &str, mode: DiffMode) -> Markup {
+    let diff = TextDiff::from_lines(old, new);
+    let groups = diff.grouped_ops(DIFF_CONTEXT_LINES);
+
+    html! {
+        @if groups.is_empty() {
+            p {"There are no differences."}
+        }
+        table."diff" {
+            @for group in &groups {
+                @let hunk = format!(
+                    "@@ -{} +{} @@",
+                    group.first().map(|op| op.old_range().start + 1).unwrap_or(0),
+                    group.first().map(|op| op.new_range().start + 1).unwrap_or(0)
+                );
+                @match mode {
+                    DiffMode::Unified => {
+                        tr."diff-hunk" { td colspan="3" {(hunk)} }
+                        @for op in group {
+                            @for change in diff.iter_changes(op) {
+                                @let (class, sign) = match change.tag() {
+                                    ChangeTag::Delete => ("diff-delete", "-"),
+                                    ChangeTag::Insert => ("diff-insert", "+"),
+                                    ChangeTag::Equal => ("diff-equal", " "),
+                                };
+                                tr class=(class) {
+                                    td."line-number" { (change.old_index().map(|index| (index + 1).to_string()).unwrap_or_default()) }
+                                    td."line-number" { (change.new_index().map(|index| (index + 1).to_string()).unwrap_or_default()) }
+                                    td { pre { (sign) (trim_line(change.value())) } }
+                                }
+                            }
+                        }
+                    }
+                    DiffMode::SideBySide => {
+                        tr."diff-hunk" { td colspan="4" {(hunk)} }
+                        @for op in group {
+                            @let old_range = op.old_range();
+                            @let new_range = op.new_range();
+                            @let rows = old_range.len().max(new_range.len());
+                            @for row in 0..rows {
+                                @let old_line = (row < old_range.len()).then_some(old_range.start + row);
+                                @let new_line = (row < new_range.len()).then_some(new_range.start + row);
+                                @let (old_class, new_class) = match op.tag() {
+                                    DiffTag::Equal => ("diff-equal", "diff-equal"),
+                                    _ => ("diff-delete", "diff-insert"),
+                                };
+                                tr {
+                                    @match old_line {
+                                        Some(line) => {
+                                            @let number = line + 1;
+                                            td."line-number" {(number)}
+                                            td class=(old_class) { pre {(trim_line(diff.old_slices()[line]))} }
+                                        }
+                                        None => { td {} td {} }
+                                    }
+                                    @match new_line {
+                                        Some(line) => {
+                                            @let number = line + 1;
+                                            td."line-number" {(number)}
+                                            td class=(new_class) { pre {(trim_line(diff.new_slices()[line]))} }
+                                        }
+                                        None => { td {} td {} }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        (PreEscaped(r#"<style>
+        table.diff {
+            border-collapse: collapse;
+            font-family: monospace;
+        }
+        table.diff td {
+            padding: 0 8px;
+            vertical-align: top;
+        }
+        table.diff pre {
+            margin: 0;
+        }
+        table.diff td.line-number {
+            color: gray;
+            text-align: right;
+            user-select: none;
+        }
+        .diff-hunk {
+            background-color: #ddf4ff;
+            color: gray;
+        }
+        .diff-delete {
+            background-color: #ffebe9;
+        }
+        .diff-insert {
+            background-color: #e6ffec;
+        }</style>"#))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::{render_diff, unified_patch, DiffMode};
+
+    #[test]
+    fn diffs() {
+        let old = "one\ntwo\nthree\n";
+        let new = "one\n<b>2</b>\nthree\nfour\n";
+
+        let patch = unified_patch(old, new, "a/paste", "b/paste");
+        assert!(patch.starts_with("--- a/paste\n+++ b/paste\n"));
+        assert!(patch.contains("-two\n"));
+        assert!(patch.contains("+<b>2</b>\n"));
+        assert!(patch.contains("+four\n"));
+
+        for mode in [DiffMode::Unified, DiffMode::SideBySide] {
+            let rendered = render_diff(old, new, mode).into_string();
+            assert!(rendered.contains("&lt;b&gt;2&lt;/b&gt;"));
+            assert!(rendered.contains("diff-insert"));
+            assert!(rendered.contains("diff-delete"));
+        }
+
+        assert!(render_diff(old, old, DiffMode::Unified)
+            .into_string()
+            .contains("There are no differences."));
+    }
+}
@@ -0,0 +1,383 @@
+use crate::paste::PasteContents;
+use crate::state_management::{save_program_state, TYRState};
+use chrono::{DateTime, Duration, Local};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use rocket::tokio::time::sleep;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// The duration in seconds the digest worker waits between checks for if a digest is due.
+pub static DIGEST_CHECK_INTERVAL: u64 = 600;
+
+/// The duration in hours between each digest email.
+pub static DIGEST_PERIOD_HOURS: i64 = 24;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// How the connection to the smtp server is secured, "none" is only meant for a local smtp sink.
+pub enum SmtpSecurity {
+    None,
+    StartTls,
+    #[default]
+    Tls,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug, Clone)]
+/// Smtp settings read from the "smtp" table of Rocket.toml, no emails are sent if the table is missing.
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// The address emails are sent from.
+    pub from: String,
+    /// The address of the host, who all emails are sent to.
+    pub to: String,
+    #[serde(default = "default_true")]
+    pub notify_new_messages: bool,
+    #[serde(default = "default_true")]
+    pub daily_digest: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// A snapshot of the program state taken when the last digest was sent, used to find what is new since then.
+pub struct DigestState {
+    pub last_sent: DateTime<Local>,
+    pub banned_ips: Vec<String>,
+    pub request_count: u64,
+}
+
+impl Default for DigestState {
+    /// Default digest state starts counting from now, so the first digest is not a summary of all time.
+    fn default() -> Self {
+        Self {
+            last_sent: Local::now(),
+            banned_ips: vec![],
+            request_count: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Summary of everything that happened on the site since the last digest.
+pub struct DigestSummary {
+    pub new_messages: usize,
+    pub new_pastes: usize,
+    pub new_uploads: usize,
+    pub new_bans: Vec<String>,
+    pub requests: u64,
+    pub active_users: usize,
+    pub total_users: usize,
+}
+
+impl DigestSummary {
+    /// Counts everything newer than the given digest state.
+    pub fn from_state(state: &TYRState, digest: &DigestState) -> Self {
+        let since = digest.last_sent;
+
+        let new_messages = state
+            .messages
+            .read()
+            .unwrap()
+            .values()
+            .flat_map(|user| user.messages.iter())
+            .filter(|message| message.time_stamp > since)
+            .count();
+
+        let (new_uploads, new_pastes) = {
+            let lock = state.pastes.read().unwrap();
+            let new = lock.values().filter(|paste| paste.post_time > since);
+            new.fold((0, 0), |(uploads, pastes), paste| match paste.content {
+                PasteContents::File(_) => (uploads + 1, pastes),
+                PasteContents::PlainText(_) | PasteContents::Redirect(_) => (uploads, pastes + 1),
+            })
+        };
+
+        let new_bans = state
+            .banned_ips
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|ip| !digest.banned_ips.contains(ip))
+            .cloned()
+            .collect();
+
+        let (requests, active_users, total_users) = {
+            let lock = state.unique_users.read().unwrap();
+            let request_count = lock.values().map(|user| user.request_count).sum::<u64>();
+            let since_system_time = SystemTime::from(since);
+            let active_users = lock
+                .values()
+                .filter_map(|user| user.last_time_seen)
+                .filter(|last_time| *last_time > since_system_time)
+                .count();
+            (
+                request_count.saturating_sub(digest.request_count),
+                active_users,
+                lock.len(),
+            )
+        };
+
+        Self {
+            new_messages,
+            new_pastes,
+            new_uploads,
+            new_bans,
+            requests,
+            active_users,
+            total_users,
+        }
+    }
+
+    /// Renders the summary into the plain text body of the digest email.
+    pub fn to_email_body(&self, since: DateTime<Local>) -> String {
+        let bans = if self.new_bans.is_empty() {
+            "none".to_string()
+        } else {
+            self.new_bans.join(", ")
+        };
+        format!(
+            "Since {}:\n\
+            New messages: {}\n\
+            New pastes: {}\n\
+            New uploads: {}\n\
+            New bans: {}\n\
+            Requests: {}\n\
+            Users seen: {} ({} unique users all time)\n",
+            since.format("%Y-%m-%d %H:%M"),
+            self.new_messages,
+            self.new_pastes,
+            self.new_uploads,
+            bans,
+            self.requests,
+            self.active_users,
+            self.total_users,
+        )
+    }
+}
+
+/// Builds the smtp transport described by the config.
+fn build_transport(
+    config: &SmtpConfig,
+) -> Result<AsyncSmtpTransport<Tokio1Executor>, lettre::transport::smtp::Error> {
+    let mut builder = match config.security {
+        SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+        SmtpSecurity::StartTls => {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?
+        }
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+    };
+    if let Some(port) = config.port {
+        builder = builder.port(port);
+    }
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    Ok(builder.build())
+}
+
+/// Sends a plain text email to the host, returning a description of the error if it could not be sent.
+pub async fn send_email(config: &SmtpConfig, subject: &str, body: String) -> Result<(), String> {
+    let from: Mailbox = config.from.parse().map_err(|err| format!("{err}"))?;
+    let to: Mailbox = config.to.parse().map_err(|err| format!("{err}"))?;
+    let email = Message::builder()
+        .from(from)
+        .to(to)
+        .subject(subject)
+        .body(body)
+        .map_err(|err| err.to_string())?;
+
+    let transport = build_transport(config).map_err(|err| err.to_string())?;
+    transport
+        .send(email)
+        .await
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+/// Emails the host about a new message in the background, if new message notifications are enabled.
+/// The text is none if the message is encrypted, in which case only the sender is mentioned.
+pub fn notify_new_message(state: &TYRState, ip: &str, text: Option<&str>) {
+    let config = match state.smtp_config.read().unwrap().clone() {
+        Some(config) if config.notify_new_messages => config,
+        _ => return,
+    };
+    let body = match text {
+        Some(text) => format!("New message from {ip}:\n\n{text}\n"),
+        None => format!("New encrypted message from {ip}, unlock the admin page to read it.\n"),
+    };
+    rocket::tokio::spawn(async move {
+        if let Err(err) = send_email(&config, "New message on thank you rocket", body).await {
+            println!("Unable to send new message email. {err}");
+        }
+    });
+}
+
+/// Sends a digest of everything since the last digest, then takes a new snapshot for the next one.
+pub async fn send_digest(state: &TYRState, config: &SmtpConfig) -> Result<(), String> {
+    let digest = state.digest_state.read().unwrap().clone();
+    let summary = DigestSummary::from_state(state, &digest);
+
+    send_email(
+        config,
+        "Daily digest for thank you rocket",
+        summary.to_email_body(digest.last_sent),
+    )
+    .await?;
+
+    *state.digest_state.write().unwrap() = DigestState {
+        last_sent: Local::now(),
+        banned_ips: state.banned_ips.read().unwrap().clone(),
+        request_count: digest.request_count + summary.requests,
+    };
+    save_program_state(state.into(), &PathBuf::from("./output/state.ser"));
+    Ok(())
+}
+
+/// Background task that emails the host a digest once every digest period.
+pub async fn digest_worker(state: TYRState) {
+    loop {
+        sleep(std::time::Duration::from_secs(DIGEST_CHECK_INTERVAL)).await;
+
+        let config = match state.smtp_config.read().unwrap().clone() {
+            Some(config) if config.daily_digest => config,
+            _ => continue,
+        };
+
+        let last_sent = state.digest_state.read().unwrap().last_sent;
+        if Local::now() - last_sent < Duration::hours(DIGEST_PERIOD_HOURS) {
+            continue;
+        }
+
+        if let Err(err) = send_digest(&state, &config).await {
+            println!("Unable to send digest email. {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+    use crate::metrics::UserMetric;
+    use crate::user::User;
+    use rocket::tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use rocket::tokio::net::TcpListener;
+
+    #[test]
+    fn test_digest_summary() {
+        let state = TYRState::default();
+        let digest = DigestState {
+            last_sent: Local::now() - Duration::hours(1),
+            banned_ips: vec!["1.1.1.1".to_string()],
+            request_count: 40,
+        };
+
+        let mut user = User::default();
+        user.push(Message::new("new message".to_string(), None, None, None).unwrap());
+        state
+            .messages
+            .write()
+            .unwrap()
+            .insert("2.2.2.2".to_string(), user);
+        state
+            .banned_ips
+            .write()
+            .unwrap()
+            .extend(["1.1.1.1".to_string(), "3.3.3.3".to_string()]);
+        state.unique_users.write().unwrap().insert(
+            "2.2.2.2".to_string(),
+            UserMetric {
+                request_count: 55,
+                logins: None,
+                last_time_seen: Some(SystemTime::now()),
+                last_page_visited: None,
+                previous_pages: None,
+            },
+        );
+
+        let summary = DigestSummary::from_state(&state, &digest);
+        assert_eq!(
+            summary,
+            DigestSummary {
+                new_messages: 1,
+                new_pastes: 0,
+                new_uploads: 0,
+                new_bans: vec!["3.3.3.3".to_string()],
+                requests: 15,
+                active_users: 1,
+                total_users: 1,
+            }
+        );
+        assert!(summary
+            .to_email_body(digest.last_sent)
+            .contains("New bans: 3.3.3.3"));
+    }
+
+    #[rocket::async_test]
+    async fn test_send_email_to_local_sink() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // a stand in smtp server that accepts a single email and returns everything it was sent.
+        let sink = rocket::tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (read, mut write) = socket.into_split();
+            let mut lines = BufReader::new(read).lines();
+            let mut received = String::new();
+            let mut in_data = false;
+            write.write_all(b"220 sink ready\r\n").await.unwrap();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                received.push_str(&line);
+                received.push('\n');
+                let reply: &[u8] = if in_data {
+                    if line != "." {
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else if line.starts_with("DATA") {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line.starts_with("QUIT") {
+                    write.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                write.write_all(reply).await.unwrap();
+            }
+            received
+        });
+
+        let config = SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port: Some(port),
+            security: SmtpSecurity::None,
+            username: None,
+            password: None,
+            from: "site@localhost".to_string(),
+            to: "host@localhost".to_string(),
+            notify_new_messages: true,
+            daily_digest: true,
+        };
+
+        send_email(&config, "test subject", "test body".to_string())
+            .await
+            .unwrap();
+
+        let received = sink.await.unwrap();
+        assert!(received.contains("RCPT TO:<host@localhost>"));
+        assert!(received.contains("Subject: test subject"));
+        assert!(received.contains("test body"));
+    }
+}
@@ -0,0 +1,163 @@
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use crypto_box::{PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The length in bytes of the salt used when deriving a key from the hosts passphrase.
+static PASSPHRASE_SALT_LENGTH: usize = 16;
+
+/// The length in bytes of a ChaCha20Poly1305 nonce.
+static NONCE_LENGTH: usize = 12;
+
+/// The duration in seconds an unlocked private key is kept without being used, before it is locked again.
+pub static MESSAGE_KEY_IDLE_TIMEOUT: u64 = 900;
+
+#[derive(Clone, Debug)]
+/// The hosts private key, unlocked by one admin login and only held in memory.
+pub struct UnlockedKey {
+    pub key: SecretKey,
+    pub last_used: Instant,
+}
+
+/// Returns the private key unlocked by the admin login, if it was used within the idle timeout, and marks it as used.
+/// Keys of every login that have been idle for too long are forgotten.
+pub fn unlocked_message_key(
+    keys: &mut HashMap<String, UnlockedKey>,
+    login: &str,
+) -> Option<SecretKey> {
+    keys.retain(|_, unlocked| {
+        unlocked.last_used.elapsed() < Duration::from_secs(MESSAGE_KEY_IDLE_TIMEOUT)
+    });
+    let unlocked = keys.get_mut(login)?;
+    unlocked.last_used = Instant::now();
+    Some(unlocked.key.clone())
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+/// The hosts key pair used to encrypt messages, all fields are base64 encoded.
+/// The private key is only ever stored encrypted with a key derived from the hosts passphrase.
+pub struct MessageEncryption {
+    pub public_key: String,
+    pub encrypted_private_key: String,
+    pub passphrase_salt: String,
+}
+
+/// Derives the key protecting the private key from the hosts passphrase.
+fn derive_passphrase_key(passphrase: &str, salt: &[u8]) -> Option<ChaCha20Poly1305> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .ok()?;
+    ChaCha20Poly1305::new_from_slice(&key).ok()
+}
+
+impl MessageEncryption {
+    /// Generates a new key pair, protecting the private key with the given passphrase.
+    pub fn generate(passphrase: &str) -> Option<Self> {
+        let secret_key = SecretKey::generate(&mut OsRng);
+
+        let mut salt = vec![0u8; PASSPHRASE_SALT_LENGTH];
+        chacha20poly1305::aead::rand_core::RngCore::fill_bytes(&mut OsRng, &mut salt);
+
+        let cipher = derive_passphrase_key(passphrase, &salt)?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut encrypted_private_key = nonce.to_vec();
+        encrypted_private_key.extend(
+            cipher
+                .encrypt(&nonce, secret_key.to_bytes().as_slice())
+                .ok()?,
+        );
+
+        Some(Self {
+            public_key: STANDARD.encode(secret_key.public_key().as_bytes()),
+            encrypted_private_key: STANDARD.encode(encrypted_private_key),
+            passphrase_salt: STANDARD.encode(salt),
+        })
+    }
+
+    /// Returns the private key if the passphrase is correct.
+    pub fn unlock(&self, passphrase: &str) -> Option<SecretKey> {
+        let salt = STANDARD.decode(&self.passphrase_salt).ok()?;
+        let encrypted = STANDARD.decode(&self.encrypted_private_key).ok()?;
+        if encrypted.len() < NONCE_LENGTH {
+            return None;
+        }
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_LENGTH);
+        let nonce: [u8; 12] = nonce.try_into().ok()?;
+
+        let cipher = derive_passphrase_key(passphrase, &salt)?;
+        let private_key: [u8; 32] = cipher
+            .decrypt(&Nonce::from(nonce), ciphertext)
+            .ok()?
+            .try_into()
+            .ok()?;
+        Some(SecretKey::from(private_key))
+    }
+
+    /// Encrypts the text to the hosts public key, returning the base64 encoded ciphertext.
+    pub fn encrypt_text(&self, text: &str) -> Option<String> {
+        let public_key: [u8; 32] = STANDARD.decode(&self.public_key).ok()?.try_into().ok()?;
+        let sealed = PublicKey::from(public_key)
+            .seal(&mut OsRng, text.as_bytes())
+            .ok()?;
+        Some(STANDARD.encode(sealed))
+    }
+}
+
+/// Decrypts base64 encoded ciphertext made by MessageEncryption::encrypt_text().
+pub fn decrypt_text(private_key: &SecretKey, ciphertext: &str) -> Option<String> {
+    let sealed = STANDARD.decode(ciphertext).ok()?;
+    let text = private_key.unseal(&sealed).ok()?;
+    String::from_utf8(text).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_and_unlock() {
+        let encryption = MessageEncryption::generate("correct horse").unwrap();
+        let ciphertext = encryption.encrypt_text("a secret message").unwrap();
+
+        assert!(!ciphertext.contains("secret"));
+        assert!(encryption.unlock("wrong passphrase").is_none());
+
+        let private_key = encryption.unlock("correct horse").unwrap();
+        assert_eq!(
+            decrypt_text(&private_key, &ciphertext).unwrap(),
+            "a secret message"
+        );
+    }
+
+    #[test]
+    fn unlocked_keys_are_per_login_and_expire() {
+        let encryption = MessageEncryption::generate("correct horse").unwrap();
+        let key = encryption.unlock("correct horse").unwrap();
+        let mut keys = HashMap::new();
+        keys.insert(
+            "admin".to_string(),
+            UnlockedKey {
+                key: key.clone(),
+                last_used: Instant::now(),
+            },
+        );
+        keys.insert(
+            "idle admin".to_string(),
+            UnlockedKey {
+                key,
+                last_used: Instant::now() - Duration::from_secs(MESSAGE_KEY_IDLE_TIMEOUT + 1),
+            },
+        );
+
+        assert!(unlocked_message_key(&mut keys, "admin").is_some());
+        assert!(unlocked_message_key(&mut keys, "other admin").is_none());
+        assert!(unlocked_message_key(&mut keys, "idle admin").is_none());
+        assert_eq!(keys.len(), 1);
+    }
+}
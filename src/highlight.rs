@@ -0,0 +1,221 @@
+use lazy_static::lazy_static;
+use maud::{html, PreEscaped};
+use std::path::Path;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Style, Theme, ThemeSet};
+use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// Pastes larger than this many bytes are shown with line numbers but without highlighting, as highlighting them is too slow.
+/// Highlighting runs on every view, so this is kept small enough that a view never holds up a worker for long.
+pub static HIGHLIGHT_SIZE_CAP: usize = 64 * 1024;
+
+/// The maximum length of a language name given by a poster.
+pub static LANGUAGE_LENGTH_CAP: usize = 32;
+
+lazy_static! {
+    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static ref THEME: Theme = ThemeSet::load_defaults()
+        .themes
+        .remove("InspiredGitHub")
+        .unwrap();
+}
+
+/// Trims a language given by a poster, discarding it if it is empty or too long.
+pub fn normalize_language(language: Option<&str>) -> Option<String> {
+    language
+        .map(|language| language.trim())
+        .filter(|language| !language.is_empty() && language.len() <= LANGUAGE_LENGTH_CAP)
+        .map(|language| language.to_string())
+}
+
+/// Picks the syntax to highlight with, in order of preference from the explicit language,
+/// the extension of the file name, and then the first line of the contents, falling back to plain text.
+pub fn detect_syntax(
+    language: Option<&str>,
+    file_name: Option<&str>,
+    contents: &str,
+) -> &'static SyntaxReference {
+    let by_language = language.and_then(|language| {
+        SYNTAX_SET
+            .find_syntax_by_token(language)
+            .or_else(|| SYNTAX_SET.find_syntax_by_name(language))
+    });
+    let by_file_name = || {
+        file_name.and_then(|file_name| {
+            let extension = Path::new(file_name)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .unwrap_or(file_name);
+            SYNTAX_SET.find_syntax_by_extension(extension)
+        })
+    };
+    let by_contents = || SYNTAX_SET.find_syntax_by_first_line(contents);
+
+    by_language
+        .or_else(by_file_name)
+        .or_else(by_contents)
+        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text())
+}
+
+/// Produces the html of each line of the contents, highlighted using the given syntax.
+pub fn highlight_lines(contents: &str, syntax: &SyntaxReference) -> Vec<String> {
+    if contents.len() > HIGHLIGHT_SIZE_CAP {
+        return contents
+            .lines()
+            .map(|line| html_escape::encode_safe(line).to_string())
+            .collect();
+    }
+
+    let mut highlighter = HighlightLines::new(syntax, &THEME);
+    LinesWithEndings::from(contents)
+        .map(|line| {
+            match highlighter.highlight_line(line, &SYNTAX_SET) {
+                Ok(regions) => {
+                    // the line endings are only needed by the parser, the table rows already separate each line.
+                    let regions = regions
+                        .into_iter()
+                        .map(|(style, text)| (style, text.trim_end_matches(['\r', '\n'])))
+                        .collect::<Vec<(Style, &str)>>();
+                    styled_line_to_highlighted_html(&regions, IncludeBackground::No)
+                        .unwrap_or_else(|_| html_escape::encode_safe(line.trim_end()).to_string())
+                }
+                Err(_) => html_escape::encode_safe(line.trim_end()).to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Renders the contents as a table of highlighted lines, each line is numbered and can be linked to with #L10, or a range with #L10-L20.
+/// Shift clicking a line number selects the range from the currently selected line.
+pub fn render_code(contents: &str, language: Option<&str>, file_name: Option<&str>) -> String {
+    let syntax = detect_syntax(language, file_name, contents);
+    let lines = highlight_lines(contents, syntax);
+
+    html! {
+        p {"Language: " (syntax.name)}
+        table."code" {
+            @for (index, line) in lines.iter().enumerate() {
+                @let number = index + 1;
+                tr id=(format!("L{}", number)) {
+                    td."line-number" { a href=(format!("#L{}", number)) {(number)} }
+                    td { pre {(PreEscaped(line))} }
+                }
+            }
+        }
+        (PreEscaped(r#"<style>
+        table.code {
+            border-collapse: collapse;
+            font-family: monospace;
+        }
+        table.code td {
+            padding: 0 8px;
+            vertical-align: top;
+        }
+        table.code pre {
+            margin: 0;
+        }
+        td.line-number {
+            text-align: right;
+            user-select: none;
+        }
+        td.line-number a {
+            color: gray;
+            text-decoration: none;
+        }
+        tr.selected-line {
+            background-color: #fff8c5;
+        }</style>"#))
+        (PreEscaped(r##"<script>
+        function selectedRange() {
+            const match = window.location.hash.match(/^#L(\d+)(?:-L(\d+))?$/);
+            if (!match) {
+                return null;
+            }
+            const start = parseInt(match[1]);
+            const end = match[2] ? parseInt(match[2]) : start;
+            return [Math.min(start, end), Math.max(start, end)];
+        }
+
+        function highlightSelectedLines() {
+            document.querySelectorAll("tr.selected-line").forEach((row) => row.classList.remove("selected-line"));
+            const range = selectedRange();
+            if (!range) {
+                return;
+            }
+            for (let number = range[0]; number <= range[1]; number++) {
+                const row = document.getElementById("L" + number);
+                if (row) {
+                    row.classList.add("selected-line");
+                }
+            }
+            const first = document.getElementById("L" + range[0]);
+            if (first) {
+                first.scrollIntoView();
+            }
+        }
+
+        document.querySelectorAll("td.line-number a").forEach((link) => {
+            link.addEventListener("click", (event) => {
+                const range = selectedRange();
+                if (event.shiftKey && range) {
+                    event.preventDefault();
+                    const number = parseInt(link.textContent);
+                    window.location.hash = "#L" + Math.min(range[0], number) + "-L" + Math.max(range[0], number);
+                }
+            });
+        });
+
+        window.addEventListener("hashchange", highlightSelectedLines);
+        highlightSelectedLines();
+        </script>"##))
+    }
+    .into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::highlight::{
+        detect_syntax, highlight_lines, normalize_language, render_code, HIGHLIGHT_SIZE_CAP,
+    };
+
+    #[test]
+    fn syntax_detection() {
+        assert_eq!(detect_syntax(Some("rust"), None, "").name, "Rust");
+        assert_eq!(
+            detect_syntax(Some("py"), Some("main.rs"), "").name,
+            "Python"
+        );
+        assert_eq!(detect_syntax(None, Some("main.rs"), "").name, "Rust");
+        assert_eq!(
+            detect_syntax(None, None, "#!/usr/bin/env python3\nprint(1)").name,
+            "Python"
+        );
+        assert_eq!(detect_syntax(None, None, "lmao").name, "Plain Text");
+        assert_eq!(
+            normalize_language(Some("  rust ")),
+            Some("rust".to_string())
+        );
+        assert_eq!(normalize_language(Some(" ")), None);
+    }
+
+    #[test]
+    fn rendered_code_is_escaped_and_numbered() {
+        let rendered = render_code("<script>alert(1)</script>\nfn main() {}\n", None, None);
+        assert!(!rendered.contains("<script>alert"));
+        assert!(rendered.contains("&lt;script&gt;"));
+        assert!(rendered.contains("id=\"L1\""));
+        assert!(rendered.contains("id=\"L2\""));
+        assert!(!rendered.contains("id=\"L3\""));
+    }
+
+    #[test]
+    fn large_pastes_are_escaped_without_highlighting() {
+        let contents = "<b>fn main() {}</b>\n".repeat(HIGHLIGHT_SIZE_CAP / 10);
+        let syntax = detect_syntax(Some("rust"), None, &contents);
+        let lines = highlight_lines(&contents, syntax);
+        assert!(lines[0].contains("&lt;b&gt;"));
+        assert!(lines.iter().all(|line| !line.contains("<span")));
+    }
+}
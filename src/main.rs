@@ -2,33 +2,58 @@
 #[macro_use]
 extern crate rocket;
 
+use crate::email::{digest_worker, SmtpConfig};
 use crate::metrics::Metrics;
 use crate::pages::admin::*;
+use crate::pages::api::*;
+use crate::pages::browse_pastes::*;
+use crate::pages::chat::*;
+use crate::pages::compare_pastes::*;
 use crate::pages::error_catch_pages::not_found;
+use crate::pages::guestbook::guestbook;
 use crate::pages::index::index;
 use crate::pages::login::*;
+use crate::pages::my_pastes::*;
 use crate::pages::new::new;
 use crate::pages::outcome_pages::*;
+use crate::pages::paste_revisions::*;
 use crate::pages::post_paste::*;
 use crate::pages::submit_message::submit_message;
 use crate::pages::view::view;
+use crate::paste_cleanup::{cleanup_worker, paste_sweeper, CleanupConfig};
+use crate::paste_counter::CounterConfig;
 use crate::state_management::*;
+use crate::webhook::{webhook_worker, WebhookConfig};
 use rocket::fairing::AdHoc;
 use rocket::fs::FileServer;
+use rocket::tokio::spawn;
 use rocket::{Build, Rocket};
 use std::fs;
 use std::path::PathBuf;
 
 // TODO: implement the usage of smol db ?
 
+mod api_token;
+mod chat;
 mod common;
+mod diff;
+mod email;
+mod encryption;
+mod highlight;
 mod message;
 mod metrics;
 mod pages;
 mod paste;
+mod paste_access;
+mod paste_cleanup;
+mod paste_counter;
+mod paste_encryption;
+mod plain_text_guard;
+mod render;
 mod state_management;
 mod user;
 mod verified_guard;
+mod webhook;
 
 /// The duration in seconds that a user must wait between each message. debug only
 #[cfg(debug_assertions)]
@@ -48,6 +73,18 @@ pub static MESSAGE_LENGTH_CAP: usize = 150;
 /// The minimum length of a message that can be left by a user.
 pub static MESSAGE_LENGTH_MIN: usize = 3;
 
+/// The maximum length of a display name shown alongside a public guestbook message.
+pub static GUESTBOOK_NAME_LENGTH_CAP: usize = 32;
+
+/// The number of approved messages shown on each page of the public guestbook.
+pub static GUESTBOOK_PAGE_SIZE: usize = 10;
+
+/// The number of pastes shown on each page of the public paste directory.
+pub static PASTE_BROWSE_PAGE_SIZE: usize = 20;
+
+/// The maximum length of a single line said in a live chat with the host.
+pub static CHAT_MESSAGE_LENGTH_CAP: usize = 500;
+
 /// The maximum length of a paste that can be left by a user.
 pub static PASTE_LENGTH_CAP: usize = 2000;
 
@@ -88,8 +125,6 @@ fn rocket() -> Rocket<Build> {
 
     // TODO: make the program periodically save its state even if its not shutting down, most likely through a second thread that carries a reference to the state.
 
-    // TODO: make the same thread that saves program state periodically also clean up old pastes, maybe of age > 30 days?
-
     rocket::build()
         .manage(state)
         .mount(
@@ -123,11 +158,53 @@ fn rocket() -> Rocket<Build> {
                 view_pastes_admin,
                 upload,
                 download_file_paste,
+                paste_created,
+                raw_paste,
+                short_link,
+                unlock_paste,
+                confirm_delete_paste,
+                delete_own_paste,
+                my_pastes,
+                pastes_with_tag,
+                browse_pastes,
+                api_create_paste,
+                api_list_pastes,
+                api_get_paste,
+                api_get_raw_paste,
+                api_delete_paste,
+                api_tokens_page,
+                create_api_token_post,
+                revoke_api_token,
+                edit_paste,
+                edit_paste_post,
+                paste_history,
+                paste_diff,
+                paste_diff_patch,
+                compare_pastes,
+                compare_pastes_patch,
                 upload_multipart,
                 view_metrics_ip,
+                guestbook,
+                guestbook_queue,
+                moderate_guestbook,
+                view_webhooks,
+                retry_webhook,
+                view_email_digest,
+                send_email_digest,
+                live_chat,
+                chat_socket,
+                admin_chat,
+                admin_chat_socket,
+                view_encryption,
+                generate_encryption_key,
+                unlock_encryption_key,
+                lock_encryption_key,
+                view_cleanup,
+                run_cleanup_now,
             ],
         )
         .register("/", catchers![not_found])
+        .register("/api", catchers![api_catcher])
         .mount("/static", FileServer::from("./static"))
         .mount("/rhythm_rs", FileServer::from("./rhythm_rs_dist")) // program crashes if static folder does not exist.
         .mount(
@@ -135,6 +212,65 @@ fn rocket() -> Rocket<Build> {
             FileServer::from("./discreet_math_fib_dist"),
         ) // program crashes if static folder does not exist.
         .attach(metrics_fairing)
+        .attach(AdHoc::on_liftoff("Webhook worker", |rocket| {
+            Box::pin(async move {
+                let state = rocket.state::<TYRState>().unwrap().clone();
+                // webhooks are optional, so a missing or malformed table just disables them.
+                let config = rocket
+                    .figment()
+                    .extract_inner::<WebhookConfig>("webhooks")
+                    .unwrap_or_default();
+                println!("Webhook urls: {:?}", config.urls);
+                // unsigned payloads could be forged by anyone who finds a receiver url, so webhooks are never sent without a secret.
+                if !config.urls.is_empty() && config.secret.trim().is_empty() {
+                    println!("WARNING: webhooks are disabled, set a secret in the webhooks table of Rocket.toml to sign them.");
+                    return;
+                }
+                *state.webhook_config.write().unwrap() = config;
+                spawn(webhook_worker(state));
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Email notifier", |rocket| {
+            Box::pin(async move {
+                let state = rocket.state::<TYRState>().unwrap().clone();
+                let config = rocket.figment().extract_inner::<SmtpConfig>("smtp").ok();
+                println!(
+                    "Smtp host: {:?}",
+                    config.as_ref().map(|config| &config.host)
+                );
+                *state.smtp_config.write().unwrap() = config;
+                spawn(digest_worker(state));
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Paste sweeper", |rocket| {
+            Box::pin(async move {
+                let state = rocket.state::<TYRState>().unwrap().clone();
+                spawn(paste_sweeper(state));
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Paste cleanup", |rocket| {
+            Box::pin(async move {
+                let state = rocket.state::<TYRState>().unwrap().clone();
+                let config = rocket
+                    .figment()
+                    .extract_inner::<CleanupConfig>("paste_cleanup")
+                    .unwrap_or_default();
+                println!("Paste cleanup config: {:?}", config);
+                *state.cleanup_config.write().unwrap() = config;
+                spawn(cleanup_worker(state));
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Paste counters", |rocket| {
+            Box::pin(async move {
+                let state = rocket.state::<TYRState>().unwrap();
+                let config = rocket
+                    .figment()
+                    .extract_inner::<CounterConfig>("paste_counters")
+                    .unwrap_or_default();
+                println!("Paste counter config: {:?}", config);
+                *state.counter_config.write().unwrap() = config;
+            })
+        }))
         .attach(AdHoc::on_shutdown("State shutdown save", |rocket| {
             Box::pin(async move {
                 println!("Saving state to file system.");
@@ -1,14 +1,21 @@
+use crate::encryption::MessageEncryption;
+use crate::pages::login::SALT;
 use crate::state_management::TYRState;
 use chrono::serde::ts_seconds;
 use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
 use rocket::State;
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use std::net::SocketAddr;
 
 #[derive(FromForm, Debug, Clone)]
 /// Form struct for a message
 pub struct NewMessage {
     pub msg: String,
+    /// True if the sender ticked "allow this to be shown publicly", unchecked boxes are simply missing from the form.
+    pub public: bool,
+    pub display_name: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -18,6 +25,98 @@ pub struct Message {
     #[serde(with = "ts_seconds")]
     pub time_stamp: DateTime<Utc>,
     pub user_hash: Option<String>, // if no user hash, display always, if user hash exists and matches, display then only.
+    pub guestbook: Option<GuestbookEntry>, // if no guestbook entry, the message is private to the sender and the host.
+    pub ciphertext: Option<String>, // if the host registered a key pair, text is empty and the message is stored encrypted here instead.
+    #[serde(default)]
+    pub text_hash: Option<String>, // keyed hash of the text of an encrypted message, so duplicates can be found without decrypting it.
+}
+
+impl Message {
+    /// Creates a message sent now, encrypting it to the hosts public key if one is registered.
+    /// Messages sent to the public guestbook are never encrypted, as their sender chose to make them public.
+    /// Returns an error if the message could not be encrypted, it is never stored in plain text instead.
+    pub fn new(
+        text: String,
+        user_hash: Option<String>,
+        guestbook: Option<GuestbookEntry>,
+        encryption: Option<&MessageEncryption>,
+    ) -> Result<Self, String> {
+        let (text, ciphertext, text_hash) = match encryption {
+            Some(encryption) if guestbook.is_none() => {
+                let ciphertext = encryption
+                    .encrypt_text(&text)
+                    .ok_or("Unable to encrypt message, the registered public key is invalid.")?;
+                (
+                    String::new(),
+                    Some(ciphertext),
+                    Some(message_text_hash(&text)),
+                )
+            }
+            _ => (text, None, None),
+        };
+
+        Ok(Self {
+            text,
+            time_stamp: Utc::now(),
+            user_hash,
+            guestbook,
+            ciphertext,
+            text_hash,
+        })
+    }
+
+    /// Returns true if the message has the same text, comparing the keyed hash for encrypted messages.
+    /// Messages encrypted before their hash was stored can not be compared, and never match.
+    pub fn has_text(&self, text: &str) -> bool {
+        match (&self.ciphertext, &self.text_hash) {
+            (None, _) => self.text == text,
+            (Some(_), Some(text_hash)) => *text_hash == message_text_hash(text),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// Hashes the text of a message with the server salt as the key, so the hash can not be checked against guessed messages without the salt.
+pub fn message_text_hash(text: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(SALT.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(text.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+/// The guestbook information of a message the sender allowed to be shown publicly.
+pub struct GuestbookEntry {
+    pub status: GuestbookStatus,
+    pub display_name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+/// The moderation status of a guestbook entry, only approved entries are shown on the public guestbook.
+pub enum GuestbookStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl GuestbookEntry {
+    /// Returns a new pending guestbook entry if the sender allowed their message to be public, trimming an empty display name away.
+    pub fn from_new_message(message: &NewMessage) -> Option<Self> {
+        if !message.public {
+            return None;
+        }
+
+        let display_name = message
+            .display_name
+            .as_ref()
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty());
+
+        Some(Self {
+            status: GuestbookStatus::Pending,
+            display_name,
+        })
+    }
 }
 
 /// A function that outputs a vector of all the messages sent by a given ip address
@@ -1,13 +1,20 @@
 use crate::common::is_ip_valid;
+use crate::email::{send_digest, DigestSummary};
+use crate::encryption::{
+    decrypt_text, unlocked_message_key, MessageEncryption, UnlockedKey, MESSAGE_KEY_IDLE_TIMEOUT,
+};
+use crate::message::GuestbookStatus;
 use crate::metrics::UserMetric;
-use crate::paste::PasteContents;
+use crate::paste::{Paste, PasteContents};
+use crate::paste_cleanup::{cleanup_now, delete_paste};
 use crate::state_management::{save_program_state, TYRState};
 use crate::user::User;
+use crate::webhook::{queue_webhook, DeliveryStatus, WebhookEvent};
 use crate::{ONLINE_TIMER, POST_COOLDOWN};
 use chrono_tz::US::Pacific;
 use maud::{html, PreEscaped};
 use rocket::form::Form;
-use rocket::http::CookieJar;
+use rocket::http::{CookieJar, Status};
 use rocket::outcome::Outcome;
 use rocket::request::FromRequest;
 use rocket::response::content::RawHtml;
@@ -17,7 +24,7 @@ use std::cmp::Ordering;
 use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 #[derive(Default)]
 /// Request guard that requires an admin cookie.
@@ -41,7 +48,7 @@ impl<'r> FromRequest<'r> for IsAdminGuard {
                 return Outcome::Success(Self(login_cookie.value().to_string()));
             }
         }
-        Outcome::Forward(())
+        Outcome::Forward(Status::Unauthorized)
     }
 }
 
@@ -228,6 +235,24 @@ pub fn view_online(_is_admin: IsAdminGuard, state: &State<TYRState>) -> RawHtml<
     )
 }
 
+/// Returns the escaped title, language and tags of a paste for the admin paste list, or nothing if it has none.
+fn paste_metadata(paste: &Paste) -> String {
+    let mut metadata = vec![];
+    if let Some(title) = &paste.title {
+        metadata.push(format!("\"{}\"", title));
+    }
+    if let Some(language) = &paste.language {
+        metadata.push(format!("language: {}", language));
+    }
+    if !paste.tags.is_empty() {
+        metadata.push(format!("tags: {}", paste.tags.join(", ")));
+    }
+    match metadata.is_empty() {
+        true => String::new(),
+        false => format!(" ({})", html_escape::encode_safe(&metadata.join(", "))),
+    }
+}
+
 #[get("/admin/view_pastes")]
 pub fn view_pastes_admin(_is_admin: IsAdminGuard, state: &State<TYRState>) -> RawHtml<String> {
     let mut paste_list = String::new();
@@ -240,7 +265,11 @@ pub fn view_pastes_admin(_is_admin: IsAdminGuard, state: &State<TYRState>) -> Ra
             "<a href=\"/paste/view/{0}/delete\">DELETE</a>",
             id_escaped_paste
         );
-        let link_to_paste = format!("<a href=\"/paste/view/{0}\">-{0}-</a>", id_escaped_paste);
+        let link_to_paste = format!(
+            "<a href=\"/paste/view/{0}\">-{0}-</a>{1}",
+            id_escaped_paste,
+            paste_metadata(paste)
+        );
         match &paste.content {
             PasteContents::File(path) => {
                 let (file_content, file_name) = match File::open(path).ok() {
@@ -266,6 +295,20 @@ pub fn view_pastes_admin(_is_admin: IsAdminGuard, state: &State<TYRState>) -> Ra
                     link_to_paste, file_name, file_content, deletion_link_for_paste
                 ));
             }
+            PasteContents::Redirect(url) => {
+                paste_list.push_str(&format!(
+                    "[{}] : Short link to {} : {} <br>",
+                    link_to_paste,
+                    html_escape::encode_safe(url),
+                    deletion_link_for_paste
+                ));
+            }
+            PasteContents::PlainText(_) if paste.client_encrypted => {
+                paste_list.push_str(&format!(
+                    "[{}] : encrypted : {} <br>",
+                    link_to_paste, deletion_link_for_paste
+                ));
+            }
             PasteContents::PlainText(paste_text) => {
                 // let paste_text = paste.text.clone();
 
@@ -303,14 +346,21 @@ pub fn view_pastes_admin(_is_admin: IsAdminGuard, state: &State<TYRState>) -> Ra
 
 #[get("/admin")]
 /// Admin only page for displaying all messages sent to the server, as well as a few tools.
-pub fn admin(_is_admin: IsAdminGuard, state: &State<TYRState>) -> RawHtml<String> {
+pub fn admin(is_admin: IsAdminGuard, state: &State<TYRState>) -> RawHtml<String> {
     let messages = state.messages.read().unwrap().clone();
+    let message_key = unlocked_message_key(&mut state.message_key.write().unwrap(), &is_admin.0);
     let message_list = {
         let mut output = String::new();
         for (ip, user) in messages {
             output.push_str(&format!("[{ip}]:<br>"));
             user.messages.iter().for_each(|message| {
-                let escaped = html_escape::encode_safe(&message.text);
+                let text = match (&message.ciphertext, &message_key) {
+                    (None, _) => message.text.clone(),
+                    (Some(ciphertext), Some(key)) => decrypt_text(key, ciphertext)
+                        .unwrap_or("[Unable to decrypt message]".to_string()),
+                    (Some(_), None) => "[Encrypted, unlock to read]".to_string(),
+                };
+                let escaped = html_escape::encode_safe(&text);
                 let hashed = {
                     match message.user_hash {
                         None => "",
@@ -336,6 +386,18 @@ pub fn admin(_is_admin: IsAdminGuard, state: &State<TYRState>) -> RawHtml<String
     let view_online_button = "<button onclick=\"window.location.href=\'/admin/view_online\';\">View Online Users</button>";
     let view_pastes_button =
         "<button onclick=\"window.location.href=\'/admin/view_pastes\';\">View Pastes</button>";
+    let webhooks_button =
+        "<button onclick=\"window.location.href=\'/admin/webhooks\';\">Webhooks</button>";
+    let email_digest_button =
+        "<button onclick=\"window.location.href=\'/admin/email_digest\';\">Email Digest</button>";
+    let encryption_button =
+        "<button onclick=\"window.location.href=\'/admin/encryption\';\">Encryption</button>";
+    let live_chat_button =
+        "<button onclick=\"window.location.href=\'/admin/chat\';\">Live Chat</button>";
+    let guestbook_queue_button =
+        "<button onclick=\"window.location.href=\'/admin/guestbook\';\">Guestbook Queue</button>";
+    let cleanup_button =
+        "<button onclick=\"window.location.href=\'/admin/cleanup\';\">Paste Cleanup</button>";
     let banned_ips = format!("{:?}", state.banned_ips.read().unwrap());
 
     let verified_list = match &state.admin_state.read().unwrap().verified_list {
@@ -390,6 +452,12 @@ pub fn admin(_is_admin: IsAdminGuard, state: &State<TYRState>) -> RawHtml<String
             (PreEscaped(view_hashes_button))
             (PreEscaped(view_online_button))
             (PreEscaped(view_pastes_button))
+            (PreEscaped(cleanup_button))
+            (PreEscaped(guestbook_queue_button))
+            (PreEscaped(webhooks_button))
+            (PreEscaped(email_digest_button))
+            (PreEscaped(live_chat_button))
+            (PreEscaped(encryption_button))
             br;
             br;
             (PreEscaped(message_list))
@@ -398,6 +466,393 @@ pub fn admin(_is_admin: IsAdminGuard, state: &State<TYRState>) -> RawHtml<String
     )
 }
 
+#[get("/admin/guestbook")]
+/// Admin only page listing every message waiting for approval before being shown on the public guestbook.
+pub fn guestbook_queue(_is_admin: IsAdminGuard, state: &State<TYRState>) -> RawHtml<String> {
+    let mut queue_string = String::new();
+
+    for (ip, user) in state.messages.read().unwrap().iter() {
+        for (index, message) in user.messages.iter().enumerate() {
+            let entry = match &message.guestbook {
+                Some(entry) if entry.status == GuestbookStatus::Pending => entry,
+                _ => continue,
+            };
+            let display_name = entry.display_name.as_deref().unwrap_or("Anonymous");
+            queue_string.push_str(&format!(
+                r#"<form action="/admin/guestbook/moderate" method="post">
+                    [{0}] {1} : {2} : {3}
+                    <input type="hidden" name="ip" value="{0}">
+                    <input type="hidden" name="index" value="{4}">
+                    <button type="submit" name="action" value="Approve">Approve</button>
+                    <button type="submit" name="action" value="Reject">Reject</button>
+                </form>"#,
+                html_escape::encode_double_quoted_attribute(ip),
+                message.time_stamp.with_timezone(&Pacific),
+                html_escape::encode_safe(display_name),
+                html_escape::encode_safe(&message.text),
+                index,
+            ));
+        }
+    }
+
+    let back_button = "<button onclick=\"window.location.href=\'/admin\';\">Go back</button>";
+
+    RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            br;
+            br;
+            @if queue_string.is_empty() {
+                p {"No messages are waiting for approval."}
+            }
+            (PreEscaped(queue_string))
+        }
+        .into_string(),
+    )
+}
+
+#[get("/admin/cleanup")]
+/// Admin only page showing the paste cleanup thresholds, and the report of the most recent cleanup run.
+pub fn view_cleanup(_is_admin: IsAdminGuard, state: &State<TYRState>) -> RawHtml<String> {
+    let config = state.cleanup_config.read().unwrap().clone();
+    let report = state.cleanup_report.read().unwrap().clone();
+
+    let format_days = |days: Option<i64>| match days.filter(|days| *days > 0) {
+        None => "never".to_string(),
+        Some(days) => format!("{} days", days),
+    };
+
+    let back_button = "<button onclick=\"window.location.href=\'/admin\';\">Go back</button>";
+
+    RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            br;
+            br;
+            p {"Pastes are deleted once older than: " (format_days(config.max_age_days))}
+            p {"Pastes are deleted once not viewed or downloaded for: " (format_days(config.max_inactive_days))}
+            p {"Pastes set to never expire are kept."}
+            p {"Cleanup runs every " (config.interval_minutes) " minutes, thresholds are set in the [<profile>.paste_cleanup] table of Rocket.toml."}
+            (PreEscaped(r#"<form action="/admin/cleanup/run" method="post"><input type="submit" value="Run cleanup now"></form>"#))
+            @match report {
+                None => {
+                    p {"No cleanup has run since launch."}
+                }
+                Some(report) => {
+                    p {"Last cleanup: " (report.time.with_timezone(&Pacific))}
+                    p {"Stale pastes deleted: " (format!("{:?}", report.stale_pastes))}
+                    p {"Pastes deleted because their file was missing: " (format!("{:?}", report.missing_file_pastes))}
+                    p {"Orphaned files deleted: " (format!("{:?}", report.orphaned_files))}
+                    p {"Empty folders deleted: " (format!("{:?}", report.removed_folders))}
+                    @if !report.errors.is_empty() {
+                        p {"Errors:"}
+                        @for error in &report.errors {
+                            (error)
+                            br;
+                        }
+                    }
+                }
+            }
+        }
+        .into_string(),
+    )
+}
+
+#[post("/admin/cleanup/run")]
+/// Route for running the paste cleanup immediately, requires an admin cookie.
+pub fn run_cleanup_now(_is_admin: IsAdminGuard, state: &State<TYRState>) -> Redirect {
+    cleanup_now(state);
+    Redirect::to(uri!("/admin/cleanup"))
+}
+
+#[get("/admin/webhooks")]
+/// Admin only page listing the configured webhook urls, and the delivery history of every webhook sent, newest first.
+pub fn view_webhooks(_is_admin: IsAdminGuard, state: &State<TYRState>) -> RawHtml<String> {
+    let urls = state.webhook_config.read().unwrap().urls.clone();
+
+    let mut deliveries_string = String::new();
+
+    for delivery in state.webhook_deliveries.read().unwrap().iter().rev() {
+        let retry_button = match delivery.status {
+            DeliveryStatus::Failed => format!(
+                "<form action=\"/admin/webhooks/retry/{}\" method=\"post\" style=\"display:inline\"><input type=\"submit\" value=\"Retry\"></form>",
+                html_escape::encode_double_quoted_attribute(&delivery.id)
+            ),
+            _ => "".to_string(),
+        };
+        deliveries_string.push_str(&format!(
+            "[{}] {} : {} : {:?} : attempts {} : {} : {} {}<br>",
+            delivery.created.with_timezone(&Pacific),
+            html_escape::encode_safe(&delivery.event_name),
+            html_escape::encode_safe(&delivery.url),
+            delivery.status,
+            delivery.attempts,
+            html_escape::encode_safe(delivery.last_response.as_deref().unwrap_or("")),
+            html_escape::encode_safe(&delivery.payload),
+            retry_button,
+        ));
+    }
+
+    let back_button = "<button onclick=\"window.location.href=\'/admin\';\">Go back</button>";
+
+    RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            br;
+            br;
+            p {"Configured webhook urls:"}
+            @if urls.is_empty() {
+                p {"No webhooks configured, add a [<profile>.webhooks] table to Rocket.toml."}
+            }
+            @for url in &urls {
+                (url)
+                br;
+            }
+            br;
+            p {"[Time] Event : Url : Status : Attempts : Last response : Payload"}
+            (PreEscaped(deliveries_string))
+        }
+        .into_string(),
+    )
+}
+
+#[post("/admin/webhooks/retry/<delivery_id>")]
+/// Route for putting a failed webhook delivery back into the retry queue, requires an admin cookie.
+pub fn retry_webhook(
+    _is_admin: IsAdminGuard,
+    state: &State<TYRState>,
+    delivery_id: String,
+) -> Redirect {
+    if let Some(delivery) = state
+        .webhook_deliveries
+        .write()
+        .unwrap()
+        .iter_mut()
+        .find(|delivery| delivery.id == delivery_id)
+    {
+        delivery.status = DeliveryStatus::Pending;
+        delivery.attempts = 0;
+        delivery.next_attempt = chrono::Local::now();
+    }
+    state.webhook_notify.notify_one();
+
+    Redirect::to(uri!("/admin/webhooks"))
+}
+
+#[get("/admin/email_digest")]
+/// Admin only page previewing the next digest email, with a button to send it right away.
+pub fn view_email_digest(_is_admin: IsAdminGuard, state: &State<TYRState>) -> RawHtml<String> {
+    let digest = state.digest_state.read().unwrap().clone();
+    let preview = DigestSummary::from_state(state, &digest).to_email_body(digest.last_sent);
+    let smtp_status = match &*state.smtp_config.read().unwrap() {
+        None => "Emails are disabled, add a [<profile>.smtp] table to Rocket.toml.".to_string(),
+        Some(config) => format!("Emails are sent to {} through {}.", config.to, config.host),
+    };
+
+    let back_button = "<button onclick=\"window.location.href=\'/admin\';\">Go back</button>";
+
+    RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            br;
+            br;
+            p { (smtp_status) }
+            pre { (preview) }
+            (PreEscaped(r#"
+            <form action="/admin/email_digest/send" method="post">
+                <input type="submit" value="Send digest now">
+            </form>
+            "#))
+        }
+        .into_string(),
+    )
+}
+
+#[post("/admin/email_digest/send")]
+/// Route for sending the digest email immediately, requires an admin cookie.
+pub async fn send_email_digest(_is_admin: IsAdminGuard, state: &State<TYRState>) -> Redirect {
+    let config = state.smtp_config.read().unwrap().clone();
+    match config {
+        None => Redirect::to(uri!("/error_message")),
+        Some(config) => match send_digest(state, &config).await {
+            Ok(_) => Redirect::to(uri!("/admin/email_digest")),
+            Err(err) => {
+                println!("Unable to send digest email. {err}");
+                Redirect::to(uri!("/error_message"))
+            }
+        },
+    }
+}
+
+#[get("/admin/encryption")]
+/// Admin only page for registering the key pair messages are encrypted to, and unlocking its private key.
+/// The private key is unlocked only for the admin login that entered the passphrase, and is locked again after MESSAGE_KEY_IDLE_TIMEOUT seconds without use.
+pub fn view_encryption(is_admin: IsAdminGuard, state: &State<TYRState>) -> RawHtml<String> {
+    let encryption = state.admin_state.read().unwrap().message_encryption.clone();
+    let is_unlocked =
+        unlocked_message_key(&mut state.message_key.write().unwrap(), &is_admin.0).is_some();
+
+    let back_button = "<button onclick=\"window.location.href=\'/admin\';\">Go back</button>";
+
+    RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            br;
+            br;
+            @match encryption {
+                None => {
+                    p {"Messages are stored in plain text. Generating a key pair encrypts every new message to its public key, and stops messages.sav from being written."}
+                    p {"The private key is stored encrypted with your passphrase, messages can not be recovered if it is forgotten."}
+                    (PreEscaped(r#"
+                    <form action="/admin/encryption/generate" method="post">
+                        <label for="passphrase">Passphrase</label>
+                        <input type="password" name="passphrase" id="passphrase">
+                        <input type="submit" value="Generate key pair">
+                    </form>
+                    "#))
+                }
+                Some(encryption) => {
+                    p {"New messages are encrypted to public key: " (encryption.public_key)}
+                    @if is_unlocked {
+                        @let idle_minutes = MESSAGE_KEY_IDLE_TIMEOUT / 60;
+                        p {"The private key is unlocked for your login only, and locks again after " (idle_minutes) " minutes without reading messages."}
+                        (PreEscaped(r#"
+                        <form action="/admin/encryption/lock" method="post">
+                            <input type="submit" value="Lock">
+                        </form>
+                        "#))
+                    } @else {
+                        (PreEscaped(r#"
+                        <form action="/admin/encryption/unlock" method="post">
+                            <label for="passphrase">Passphrase</label>
+                            <input type="password" name="passphrase" id="passphrase">
+                            <input type="submit" value="Unlock">
+                        </form>
+                        "#))
+                    }
+                }
+            }
+        }
+        .into_string(),
+    )
+}
+
+#[derive(FromForm, Debug, Clone)]
+/// Form for the passphrase protecting the hosts private key.
+pub struct Passphrase {
+    pub passphrase: String,
+}
+
+#[post("/admin/encryption/generate", data = "<passphrase>")]
+/// Route for generating the key pair messages are encrypted to, a key pair can only be generated once,
+/// as replacing it would make every message encrypted to the old key unreadable.
+pub fn generate_encryption_key(
+    is_admin: IsAdminGuard,
+    state: &State<TYRState>,
+    passphrase: Form<Passphrase>,
+) -> Redirect {
+    if passphrase.passphrase.is_empty() {
+        return Redirect::to(uri!("/error_message"));
+    }
+
+    {
+        let mut lock = state.admin_state.write().unwrap();
+        if lock.message_encryption.is_some() {
+            return Redirect::to(uri!("/error_message"));
+        }
+        match MessageEncryption::generate(&passphrase.passphrase) {
+            None => return Redirect::to(uri!("/error_message")),
+            Some(encryption) => {
+                if let Some(key) = encryption.unlock(&passphrase.passphrase) {
+                    unlock_for(state, &is_admin.0, key);
+                }
+                lock.message_encryption = Some(encryption);
+            }
+        }
+    }
+
+    save_program_state(state, &PathBuf::from("./output/state.ser"));
+
+    Redirect::to(uri!("/admin/encryption"))
+}
+
+#[post("/admin/encryption/unlock", data = "<passphrase>")]
+/// Route for unlocking the hosts private key for the admin login, which is then held in memory until locked, left idle for too long, or the program restarts.
+pub fn unlock_encryption_key(
+    is_admin: IsAdminGuard,
+    state: &State<TYRState>,
+    passphrase: Form<Passphrase>,
+) -> Redirect {
+    let encryption = state.admin_state.read().unwrap().message_encryption.clone();
+    match encryption.and_then(|encryption| encryption.unlock(&passphrase.passphrase)) {
+        None => Redirect::to(uri!("/error_message")),
+        Some(key) => {
+            unlock_for(state, &is_admin.0, key);
+            Redirect::to(uri!("/admin"))
+        }
+    }
+}
+
+/// Holds the unlocked private key in memory for the admin login.
+fn unlock_for(state: &State<TYRState>, login: &str, key: crypto_box::SecretKey) {
+    state.message_key.write().unwrap().insert(
+        login.to_string(),
+        UnlockedKey {
+            key,
+            last_used: Instant::now(),
+        },
+    );
+}
+
+#[post("/admin/encryption/lock")]
+/// Route for forgetting the private key unlocked by the admin login.
+pub fn lock_encryption_key(is_admin: IsAdminGuard, state: &State<TYRState>) -> Redirect {
+    state.message_key.write().unwrap().remove(&is_admin.0);
+    Redirect::to(uri!("/admin/encryption"))
+}
+
+#[derive(FromFormField, Debug, Clone)]
+/// Enum for the moderation action taken on a guestbook entry.
+pub enum GuestbookAction {
+    Approve,
+    Reject,
+}
+
+#[derive(FromForm, Debug, Clone)]
+/// Form identifying a message by the ip that sent it and its position in that users message list.
+pub struct GuestbookModeration {
+    pub ip: String,
+    pub index: usize,
+    pub action: GuestbookAction,
+}
+
+#[post("/admin/guestbook/moderate", data = "<moderation>")]
+/// Route for approving or rejecting a message from the guestbook queue, requires an admin cookie.
+pub fn moderate_guestbook(
+    _is_admin: IsAdminGuard,
+    state: &State<TYRState>,
+    moderation: Form<GuestbookModeration>,
+) -> Redirect {
+    {
+        let mut lock = state.messages.write().unwrap();
+        let entry = lock
+            .get_mut(&moderation.ip)
+            .and_then(|user| user.messages.get_mut(moderation.index))
+            .and_then(|message| message.guestbook.as_mut());
+
+        if let Some(entry) = entry {
+            entry.status = match moderation.action {
+                GuestbookAction::Approve => GuestbookStatus::Approved,
+                GuestbookAction::Reject => GuestbookStatus::Rejected,
+            };
+        }
+    }
+
+    save_program_state(state, &PathBuf::from("./output/state.ser"));
+
+    Redirect::to(uri!("/admin/guestbook"))
+}
+
 #[derive(FromFormField, Debug, Clone)]
 /// Enum for determining the action to go through with, used for submitting an ip address in a form.
 pub enum IpAction {
@@ -422,6 +877,7 @@ pub fn ban_ip(_is_admin: IsAdminGuard, state: &State<TYRState>, ip: Form<Ip>) ->
         IpAction::Ban => {
             if is_ip_valid(&ip.ip) {
                 state.banned_ips.write().unwrap().push(ip.ip.clone());
+                queue_webhook(state, WebhookEvent::Ban { ip: ip.ip.clone() });
             }
         }
         IpAction::Unban => {
@@ -478,7 +934,7 @@ pub fn ban_ip(_is_admin: IsAdminGuard, state: &State<TYRState>, ip: Form<Ip>) ->
 /// Returns true if the user is an admin.
 /// Requirements for this are the state holding the login cookie of the user in the admin_hashes vector.
 pub fn check_is_admin(state: &State<TYRState>, jar: &CookieJar) -> bool {
-    return match jar.get("login") {
+    match jar.get("login") {
         None => false,
         Some(cookie) => state
             .admin_state
@@ -486,7 +942,7 @@ pub fn check_is_admin(state: &State<TYRState>, jar: &CookieJar) -> bool {
             .unwrap()
             .admin_hashes
             .contains(&cookie.value().to_string()),
-    };
+    }
 }
 
 #[get("/admin/metrics/<ip_address>")]
@@ -555,13 +1011,12 @@ pub fn view_metrics_ip(
 /// Route for deleting a paste, this is forceful and requires administration rights.
 /// The paste is either deleted, or if it does not exist, a 404 error is returned.
 pub fn force_delete_paste(
-    paste_id: u64,
+    paste_id: String,
     state: &State<TYRState>,
     _is_admin_guard: IsAdminGuard,
 ) -> Redirect {
-    let mut lock = state.pastes.write().unwrap();
-    return match lock.remove(&paste_id.to_string()) {
+    match delete_paste(state, &paste_id) {
         None => Redirect::to(uri!("/paste_404")),
         Some(_paste) => Redirect::to(uri!("/admin")),
-    };
+    }
 }
@@ -0,0 +1,599 @@
+use crate::api_token::{create_api_token, ApiAuth};
+use crate::pages::post_paste::{
+    counted_visitor, insert_paste_at, remove_if_expired, take_raw_paste, valid_custom_url,
+    viewer_has_password_access, RawPaste,
+};
+use crate::paste::{Paste, PasteExpiry, PasteOptions, PasteVisibility};
+use crate::paste_access::try_paste_password;
+use crate::paste_cleanup::delete_paste;
+use crate::state_management::{save_program_state, TYRState};
+use crate::webhook::{queue_webhook, WebhookEvent};
+use crate::{PASTE_LENGTH_CAP, PASTE_LENGTH_MIN};
+use chrono::{DateTime, Local};
+use chrono_tz::US::Pacific;
+use maud::{html, PreEscaped};
+use rocket::form::Form;
+use rocket::http::{CookieJar, Status};
+use rocket::request::FlashMessage;
+use rocket::response::content::RawHtml;
+use rocket::response::status::{Created, Custom};
+use rocket::response::{Flash, Redirect};
+use rocket::serde::json::Json;
+use rocket::{Request, State};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+
+/// The kind of the flash message holding a newly generated api token.
+pub static API_TOKEN_FLASH: &str = "api_token";
+
+#[derive(Serialize, Debug)]
+/// The json body of every error returned by the api.
+pub struct ApiError {
+    pub error: String,
+}
+
+/// An error response of the api, a status code with a json body describing the error.
+type ApiResult<T> = Result<T, Custom<Json<ApiError>>>;
+
+/// Builds an error response of the api.
+fn api_error(status: Status, error: &str) -> Custom<Json<ApiError>> {
+    Custom(
+        status,
+        Json(ApiError {
+            error: error.to_string(),
+        }),
+    )
+}
+
+#[derive(Deserialize, Debug)]
+/// The json body for creating a paste through the api, every field but the content is optional.
+pub struct ApiNewPaste {
+    pub content: String,
+    pub custom_url: Option<String>,
+    pub expiry: Option<PasteExpiry>,
+    pub max_views: Option<u32>,
+    #[serde(default)]
+    pub burn_after_reading: bool,
+    pub language: Option<String>,
+    pub visibility: Option<PasteVisibility>,
+    #[serde(default)]
+    pub allowed_logins: Vec<String>,
+    pub password: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub list_publicly: bool,
+}
+
+impl ApiNewPaste {
+    /// Returns the options the poster chose for this paste.
+    pub fn options(&self) -> PasteOptions {
+        PasteOptions {
+            expiry: self.expiry,
+            max_views: self.max_views,
+            burn_after_reading: self.burn_after_reading,
+            language: self.language.clone(),
+            visibility: self.visibility,
+            allowed_logins: Some(self.allowed_logins.join(",")),
+            password: self.password.clone(),
+            title: self.title.clone(),
+            description: self.description.clone(),
+            tags: Some(self.tags.join(",")),
+            list_publicly: self.list_publicly,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+/// The metadata of a paste returned by the api, never including its contents.
+pub struct ApiPaste {
+    pub id: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub view_path: String,
+    pub raw_path: String,
+    pub post_time: DateTime<Local>,
+    pub last_edited: Option<DateTime<Local>>,
+    pub expires_at: Option<DateTime<Local>>,
+    pub max_views: Option<u32>,
+    pub views_remaining: Option<u32>,
+    pub view_count: u32,
+    pub download_count: u32,
+    pub unique_visitors: u32, // only counted when the server is set to count unique visitors
+    pub language: Option<String>,
+    pub visibility: PasteVisibility,
+    pub listed_publicly: bool,
+    pub has_password: bool,
+    pub client_encrypted: bool, // the raw contents are ciphertext, only the link the poster shared holds the key
+    pub revision_count: usize,
+    pub forked_from: Option<String>,
+}
+
+impl ApiPaste {
+    pub fn from_paste(paste_id: &str, paste: &Paste) -> Self {
+        ApiPaste {
+            id: paste_id.to_string(),
+            title: paste.title.clone(),
+            description: paste.description.clone(),
+            tags: paste.tags.clone(),
+            view_path: format!("/paste/view/{}", paste_id),
+            raw_path: format!("/api/v1/pastes/{}/raw", paste_id),
+            post_time: paste.post_time,
+            last_edited: paste.last_edited,
+            expires_at: paste.expires_at,
+            max_views: paste.max_views,
+            views_remaining: paste.views_remaining(),
+            view_count: paste.view_count.get(),
+            download_count: paste.download_count.get(),
+            unique_visitors: paste.visitors.len() as u32,
+            language: paste.language.clone(),
+            visibility: paste.visibility,
+            listed_publicly: paste.listed_publicly,
+            has_password: paste.password_hash.is_some(),
+            client_encrypted: paste.client_encrypted,
+            revision_count: paste.revision_count(),
+            forked_from: paste.forked_from.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+/// The response to creating a paste, the deletion token is only ever returned here.
+pub struct ApiCreatedPaste {
+    #[serde(flatten)]
+    pub paste: ApiPaste,
+    pub deletion_token: String,
+}
+
+/// Returns the login of the api token, or an unauthorized error if no token was given.
+fn require_login(auth: &ApiAuth) -> ApiResult<&str> {
+    auth.0.as_deref().ok_or_else(|| {
+        api_error(
+            Status::Unauthorized,
+            "This route requires an api token, given as Authorization: Bearer <token>.",
+        )
+    })
+}
+
+/// Returns an error if the paste does not exist, has expired, or can not be viewed by the login.
+fn check_can_view(state: &State<TYRState>, paste_id: &str, login: Option<&str>) -> ApiResult<()> {
+    if remove_if_expired(state, paste_id) {
+        return Err(api_error(Status::NotFound, "Paste not found."));
+    }
+    match state.pastes.read().unwrap().get(paste_id) {
+        None => Err(api_error(Status::NotFound, "Paste not found.")),
+        Some(paste) if !paste.can_view(login, false) => {
+            Err(api_error(Status::Forbidden, "This paste is private."))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// Returns an error if the paste is password protected, and neither the password nor an access cookie from entering it on the site was given.
+/// Wrong passwords are counted against the ip, the same as on the unlock page.
+fn check_password(
+    state: &State<TYRState>,
+    jar: &CookieJar,
+    ip: IpAddr,
+    paste_id: &str,
+    password: Option<&str>,
+) -> ApiResult<()> {
+    let has_access = viewer_has_password_access(state, jar, paste_id)
+        || password
+            .map(|password| try_paste_password(state, ip, paste_id, password))
+            .unwrap_or(false);
+    match has_access {
+        true => Ok(()),
+        false => Err(api_error(
+            Status::Forbidden,
+            "This paste is password protected, give its password with ?password=",
+        )),
+    }
+}
+
+#[post("/api/v1/pastes", format = "json", data = "<new_paste>")]
+/// Api route for creating a paste, responding with its metadata and deletion token.
+/// curl -H "Authorization: Bearer <token>" -H "Content-Type: application/json" -d '{"content": "hello"}' http://localhost:8080/api/v1/pastes
+pub fn api_create_paste(
+    new_paste: Json<ApiNewPaste>,
+    auth: ApiAuth,
+    req: SocketAddr,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> ApiResult<Created<Json<ApiCreatedPaste>>> {
+    let login = require_login(&auth)?;
+
+    // the token acts as its login, even if the request has no login cookie.
+    let mut paste = Paste::new(new_paste.content.clone(), &req, jar);
+    paste.login_cookie_of_poster = Some(format!("login={}", login));
+    let mut paste = new_paste
+        .options()
+        .apply(paste)
+        .map_err(|err| api_error(Status::BadRequest, &err))?;
+
+    let is_verified = state
+        .admin_state
+        .read()
+        .unwrap()
+        .verified_list
+        .as_ref()
+        .map(|verified_list| {
+            verified_list.contains(&login.to_string())
+                || verified_list.contains(&req.ip().to_string())
+        })
+        .unwrap_or(false);
+    // require unverified users paste to meet requirements of length
+    if !is_verified && new_paste.content.len() > PASTE_LENGTH_CAP {
+        return Err(api_error(Status::PayloadTooLarge, "Paste is too long."));
+    }
+    if !is_verified && new_paste.content.len() < PASTE_LENGTH_MIN {
+        return Err(api_error(Status::BadRequest, "Paste is too short."));
+    }
+
+    let deletion_token = paste.create_deletion_token();
+    let custom_url = valid_custom_url(new_paste.custom_url.as_deref(), is_verified);
+    let created = {
+        let mut lock = state.pastes.write().unwrap();
+        let paste_id = insert_paste_at(&mut lock, paste, custom_url);
+        ApiPaste::from_paste(&paste_id, &lock[&paste_id])
+    };
+
+    queue_webhook(
+        state,
+        WebhookEvent::NewPaste {
+            paste_id: created.id.clone(),
+            ip: req.ip().to_string(),
+        },
+    );
+    Ok(
+        Created::new(created.view_path.clone()).body(Json(ApiCreatedPaste {
+            paste: created,
+            deletion_token,
+        })),
+    )
+}
+
+#[get("/api/v1/pastes")]
+/// Api route listing the metadata of every paste created by the login of the api token, newest first.
+pub fn api_list_pastes(auth: ApiAuth, state: &State<TYRState>) -> ApiResult<Json<Vec<ApiPaste>>> {
+    let login = require_login(&auth)?;
+    let mut pastes = state
+        .pastes
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(_, paste)| paste.poster_login() == Some(login) && !paste.is_expired())
+        .map(|(paste_id, paste)| ApiPaste::from_paste(paste_id, paste))
+        .collect::<Vec<ApiPaste>>();
+    pastes.sort_by_key(|paste| Reverse(paste.post_time));
+    Ok(Json(pastes))
+}
+
+#[get("/api/v1/pastes/<paste_id>?<password>")]
+/// Api route for the metadata of a paste, reading the metadata does not count as a view.
+/// Password protected pastes take their password in the query, the same as the raw contents.
+pub fn api_get_paste(
+    paste_id: String,
+    password: Option<String>,
+    req: SocketAddr,
+    auth: ApiAuth,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> ApiResult<Json<ApiPaste>> {
+    check_can_view(state, &paste_id, auth.0.as_deref())?;
+    check_password(state, jar, req.ip(), &paste_id, password.as_deref())?;
+    state
+        .pastes
+        .read()
+        .unwrap()
+        .get(&paste_id)
+        .map(|paste| Json(ApiPaste::from_paste(&paste_id, paste)))
+        .ok_or_else(|| api_error(Status::NotFound, "Paste not found."))
+}
+
+#[get("/api/v1/pastes/<paste_id>/raw?<password>")]
+/// Api route for the contents of a paste, counted as a view.
+/// Password protected pastes take their password in the query, e.g. /api/v1/pastes/<paste_id>/raw?password=hunter2
+pub fn api_get_raw_paste(
+    paste_id: String,
+    password: Option<String>,
+    req: SocketAddr,
+    auth: ApiAuth,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> ApiResult<RawPaste> {
+    check_can_view(state, &paste_id, auth.0.as_deref())?;
+    check_password(state, jar, req.ip(), &paste_id, password.as_deref())?;
+    let visitor = counted_visitor(state, auth.0.as_deref(), req.ip());
+    take_raw_paste(state, &paste_id, visitor.as_deref())
+        .map_err(|status| api_error(status, "Unable to read paste."))
+}
+
+#[delete("/api/v1/pastes/<paste_id>?<token>")]
+/// Api route for deleting a paste, allowed for the login that created it, or with the deletion token of the paste.
+pub fn api_delete_paste(
+    paste_id: String,
+    token: Option<String>,
+    auth: ApiAuth,
+    state: &State<TYRState>,
+) -> ApiResult<Status> {
+    let can_delete = match state.pastes.read().unwrap().get(&paste_id) {
+        None => return Err(api_error(Status::NotFound, "Paste not found.")),
+        Some(paste) => {
+            let is_poster = auth.0.is_some() && paste.poster_login() == auth.0.as_deref();
+            let has_token = token
+                .as_deref()
+                .map(|token| paste.check_deletion_token(token))
+                .unwrap_or(false);
+            is_poster || has_token
+        }
+    };
+    if !can_delete {
+        return Err(api_error(
+            Status::Forbidden,
+            "Only the poster of a paste, or the holder of its deletion token, can delete it.",
+        ));
+    }
+    delete_paste(state, &paste_id);
+    Ok(Status::NoContent)
+}
+
+#[catch(default)]
+/// Catcher for the api, so errors from rocket itself are also returned as json.
+pub fn api_catcher(status: Status, _req: &Request) -> Custom<Json<ApiError>> {
+    api_error(status, status.reason().unwrap_or("Unknown error."))
+}
+
+#[derive(FromForm, Debug, Clone)]
+/// Form struct for generating a new api token.
+pub struct NewApiToken {
+    pub label: String,
+}
+
+#[get("/paste/api_tokens")]
+/// Page for logged in users to generate and revoke the api tokens used by the paste api.
+pub fn api_tokens_page(
+    state: &State<TYRState>,
+    jar: &CookieJar,
+    flash: Option<FlashMessage<'_>>,
+) -> RawHtml<String> {
+    let back_button = "<button onclick=\"window.location.href=\'/paste/mine\';\">Go back</button>";
+    let login = match jar.get("login") {
+        None => {
+            return RawHtml(
+                html! {
+                    (PreEscaped(back_button))
+                    p {"Log in to generate api tokens."}
+                }
+                .into_string(),
+            )
+        }
+        Some(cookie) => cookie.value().to_string(),
+    };
+
+    let mut tokens = state
+        .api_tokens
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(_, token)| token.login == login)
+        .map(|(hash, token)| (hash.clone(), token.clone()))
+        .collect::<Vec<_>>();
+    tokens.sort_by_key(|(_, token)| token.created_at);
+    let format_time = |time: DateTime<Local>| {
+        time.with_timezone(&Pacific)
+            .format("%Y-%m-%d %H:%M:%S %Z")
+            .to_string()
+    };
+
+    RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            @if let Some(flash) = flash {
+                @if flash.kind() == API_TOKEN_FLASH {
+                    p {"Your new api token is " b {(flash.message())} ", copy it now as it will not be shown again."}
+                } @else {
+                    p {(flash.message())}
+                }
+            }
+            p {"Api tokens act as your login when using the paste api, send them as Authorization: Bearer <token>."}
+            form action="/paste/api_tokens" method="post" {
+                label for="label" {"Label "}
+                input type="text" name="label" id="label";
+                input type="submit" value="Generate token";
+            }
+            @if tokens.is_empty() {
+                p {"You have no api tokens."}
+            } @else {
+                table {
+                    tr {
+                        th {"Label"}
+                        th {"Created"}
+                        th {"Last used"}
+                        th {}
+                    }
+                    @for (hash, token) in &tokens {
+                        @let revoke_url = format!("/paste/api_tokens/revoke/{}", hash);
+                        tr {
+                            td {(token.label)}
+                            td {(format_time(token.created_at))}
+                            td {(token.last_used.map(format_time).unwrap_or_else(|| "Never".to_string()))}
+                            td {
+                                form action=(revoke_url) method="post" {
+                                    input type="submit" value="Revoke";
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        .into_string(),
+    )
+}
+
+#[post("/paste/api_tokens", data = "<new_token>")]
+/// Route for generating an api token, the token is shown once through a flash message.
+pub fn create_api_token_post(
+    new_token: Form<NewApiToken>,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> Flash<Redirect> {
+    let redirect = Redirect::to(uri!(api_tokens_page));
+    let login = match jar.get("login") {
+        None => return Flash::error(redirect, "Log in to generate api tokens."),
+        Some(cookie) => cookie.value().to_string(),
+    };
+    let created = create_api_token(
+        &mut state.api_tokens.write().unwrap(),
+        &login,
+        &new_token.label,
+    );
+    match created {
+        Ok(token) => {
+            save_program_state(state, &PathBuf::from("./output/state.ser"));
+            Flash::new(redirect, API_TOKEN_FLASH, token)
+        }
+        Err(err) => Flash::error(redirect, err),
+    }
+}
+
+#[post("/paste/api_tokens/revoke/<token_hash>")]
+/// Route for revoking one of the users api tokens.
+pub fn revoke_api_token(
+    token_hash: String,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> Flash<Redirect> {
+    let redirect = Redirect::to(uri!(api_tokens_page));
+    let login = jar.get("login").map(|cookie| cookie.value().to_string());
+    {
+        let mut tokens = state.api_tokens.write().unwrap();
+        let is_own_token = tokens
+            .get(&token_hash)
+            .map(|token| Some(&token.login) == login.as_ref())
+            .unwrap_or(false);
+        if !is_own_token {
+            return Flash::error(redirect, "Unable to revoke api token.");
+        }
+        tokens.remove(&token_hash);
+    } // block for locking the tokens in write mode, released before the state is saved.
+
+    save_program_state(state, &PathBuf::from("./output/state.ser"));
+    Flash::success(redirect, "Api token revoked.")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api_token::create_api_token;
+    use crate::pages::api::{api_create_paste, api_delete_paste, api_get_paste};
+    use crate::state_management::TYRState;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::blocking::Client;
+    use serde_json::Value;
+
+    #[test]
+    fn delete_with_deletion_token() {
+        let state = TYRState::default();
+        let api_token =
+            create_api_token(&mut state.api_tokens.write().unwrap(), "login", "test").unwrap();
+        let pastes = state.pastes.clone();
+        let rocket = rocket::build()
+            .manage(state)
+            .mount("/", routes![api_create_paste, api_delete_paste]);
+        let client = Client::tracked(rocket).unwrap();
+
+        let created = client
+            .post("/api/v1/pastes")
+            .remote("127.0.0.1:8000".parse().unwrap())
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", api_token),
+            ))
+            .body(r#"{"content": "a paste to delete"}"#)
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        let paste_id = created["id"].as_str().unwrap();
+        let deletion_token = created["deletion_token"].as_str().unwrap();
+
+        // the deletion token is not an api token, so it is refused as bearer auth.
+        let response = client
+            .delete(format!("/api/v1/pastes/{}", paste_id))
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", deletion_token),
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .delete(format!("/api/v1/pastes/{}?token=wrong", paste_id))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client
+            .delete(format!(
+                "/api/v1/pastes/{}?token={}",
+                paste_id, deletion_token
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+        assert!(pastes.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn metadata_needs_the_paste_password() {
+        let state = TYRState::default();
+        let api_token =
+            create_api_token(&mut state.api_tokens.write().unwrap(), "login", "test").unwrap();
+        let rocket = rocket::build()
+            .manage(state)
+            .mount("/", routes![api_create_paste, api_get_paste]);
+        let client = Client::tracked(rocket).unwrap();
+
+        let created = client
+            .post("/api/v1/pastes")
+            .remote("127.0.0.1:8000".parse().unwrap())
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", api_token),
+            ))
+            .body(r#"{"content": "a protected paste", "password": "hunter2"}"#)
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        let paste_id = created["id"].as_str().unwrap();
+
+        let status = |uri: String| {
+            client
+                .get(uri)
+                .remote("127.0.0.1:8000".parse().unwrap())
+                .dispatch()
+                .status()
+        };
+        assert_eq!(
+            status(format!("/api/v1/pastes/{}", paste_id)),
+            Status::Forbidden
+        );
+        assert_eq!(
+            status(format!("/api/v1/pastes/{}?password=hunter3", paste_id)),
+            Status::Forbidden
+        );
+        assert_eq!(
+            status(format!("/api/v1/pastes/{}?password=hunter2", paste_id)),
+            Status::Ok
+        );
+        assert_eq!(
+            status("/api/v1/pastes/missing".to_string()),
+            Status::NotFound
+        );
+    }
+}
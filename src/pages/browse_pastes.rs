@@ -0,0 +1,274 @@
+use crate::paste::{normalize_tags, Paste, PasteVisibility};
+use crate::state_management::TYRState;
+use crate::PASTE_BROWSE_PAGE_SIZE;
+use chrono::{DateTime, Local};
+use chrono_tz::US::Pacific;
+use maud::{html, PreEscaped};
+use rocket::response::content::RawHtml;
+use rocket::State;
+use std::cmp::Reverse;
+
+/// Returns true if the paste can be shown in public listings of pastes, which the poster has to opt in to.
+/// Unlisted, private and password protected pastes are only ever reached through their link.
+pub fn is_publicly_listed(paste: &Paste) -> bool {
+    paste.listed_publicly
+        && paste.visibility == PasteVisibility::Public
+        && paste.password_hash.is_none()
+        && !paste.is_expired()
+}
+
+#[derive(FromFormField, Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// The orders the public paste directory can be sorted in.
+pub enum BrowseSort {
+    #[default]
+    Newest,
+    Oldest,
+    MostViewed,
+    Largest,
+    Title,
+}
+
+impl BrowseSort {
+    /// The name of the sort used in the query of the directory.
+    fn name(&self) -> &'static str {
+        match self {
+            BrowseSort::Newest => "Newest",
+            BrowseSort::Oldest => "Oldest",
+            BrowseSort::MostViewed => "MostViewed",
+            BrowseSort::Largest => "Largest",
+            BrowseSort::Title => "Title",
+        }
+    }
+}
+
+/// Returns how long ago the time was, e.g. "3 hours ago".
+fn age(time: DateTime<Local>) -> String {
+    let seconds = Local::now()
+        .signed_duration_since(time)
+        .num_seconds()
+        .max(0);
+    let (amount, unit) = match seconds {
+        0..=59 => (seconds, "second"),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    match amount {
+        1 => format!("1 {unit} ago"),
+        amount => format!("{amount} {unit}s ago"),
+    }
+}
+
+/// Returns the size in bytes in a readable unit, e.g. "1.5 KiB".
+fn readable_size(size: u64) -> String {
+    match size {
+        0..=1023 => format!("{size} B"),
+        1024..=1048575 => format!("{:.1} KiB", size as f64 / 1024.0),
+        _ => format!("{:.1} MiB", size as f64 / 1048576.0),
+    }
+}
+
+#[get("/paste/browse?<page>&<sort>")]
+/// Public directory of the pastes their posters chose to list publicly, paginated and sortable.
+pub fn browse_pastes(
+    page: Option<usize>,
+    sort: Option<BrowseSort>,
+    state: &State<TYRState>,
+) -> RawHtml<String> {
+    let back_button = "<button onclick=\"window.location.href=\'/\';\">Go back</button>";
+    let page = page.unwrap_or_default();
+    let sort = sort.unwrap_or_default();
+    let unique_visitors = state.counter_config.read().unwrap().unique_visitors;
+
+    // the pastes are borrowed for the whole page rather than cloned, so a large directory is never copied per request.
+    let lock = state.pastes.read().unwrap();
+    let mut pastes = lock
+        .iter()
+        .filter(|(_, paste)| is_publicly_listed(paste))
+        .map(|(paste_id, paste)| (paste_id, paste, paste.size()))
+        .collect::<Vec<(&String, &Paste, u64)>>();
+    match sort {
+        BrowseSort::Newest => pastes.sort_by_key(|(_, paste, _)| Reverse(paste.post_time)),
+        BrowseSort::Oldest => pastes.sort_by_key(|(_, paste, _)| paste.post_time),
+        BrowseSort::MostViewed => {
+            pastes.sort_by_key(|(_, paste, _)| Reverse(paste.displayed_views(unique_visitors)))
+        }
+        BrowseSort::Largest => pastes.sort_by_key(|(_, _, size)| Reverse(*size)),
+        BrowseSort::Title => pastes.sort_by_key(|(paste_id, paste, _)| {
+            paste
+                .title
+                .clone()
+                .unwrap_or(paste_id.to_string())
+                .to_lowercase()
+        }),
+    }
+
+    let page_count = pastes.len().div_ceil(PASTE_BROWSE_PAGE_SIZE);
+    let entries = pastes
+        .into_iter()
+        .skip(page.saturating_mul(PASTE_BROWSE_PAGE_SIZE))
+        .take(PASTE_BROWSE_PAGE_SIZE)
+        .collect::<Vec<_>>();
+
+    let previous_page = format!(
+        "/paste/browse?page={}&sort={}",
+        page.saturating_sub(1),
+        sort.name()
+    );
+    let next_page = format!(
+        "/paste/browse?page={}&sort={}",
+        page.saturating_add(1),
+        sort.name()
+    );
+    let sorts = [
+        (BrowseSort::Newest, "Newest"),
+        (BrowseSort::Oldest, "Oldest"),
+        (BrowseSort::MostViewed, "Most viewed"),
+        (BrowseSort::Largest, "Largest"),
+        (BrowseSort::Title, "Title"),
+    ];
+
+    RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            h2 {"Public pastes"}
+            p {
+                "Sort by: "
+                @for (option, label) in sorts {
+                    @if option == sort {
+                        b {(label)} " "
+                    } @else {
+                        @let sort_link = format!("/paste/browse?sort={}", option.name());
+                        a href=(sort_link) {(label)} " "
+                    }
+                }
+            }
+            @if entries.is_empty() {
+                p {"There are no public pastes to show."}
+            } @else {
+                table {
+                    tr {
+                        th {"Title"}
+                        th {"Language"}
+                        th {"Size"}
+                        th {"Age"}
+                        th {"Views"}
+                    }
+                    @for (paste_id, paste, size) in &entries {
+                        @let view_link = format!("/paste/view/{}", paste_id);
+                        tr {
+                            td { a href=(view_link) {(paste.title.clone().unwrap_or_else(|| paste_id.to_string()))} }
+                            td {(paste.language.clone().unwrap_or_default())}
+                            td {(readable_size(*size))}
+                            td {(age(paste.post_time))}
+                            td {(paste.displayed_views(unique_visitors))}
+                        }
+                    }
+                }
+            }
+            @if page > 0 {
+                a href=(previous_page) {"Previous"}
+                " "
+            }
+            @if page.saturating_add(1) < page_count {
+                a href=(next_page) {"Next"}
+            }
+        }
+        .into_string(),
+    )
+}
+
+#[get("/paste/tag/<tag>")]
+/// Page listing the publicly listed pastes with the given tag, newest first.
+pub fn pastes_with_tag(tag: String, state: &State<TYRState>) -> RawHtml<String> {
+    let back_button = "<button onclick=\"window.location.href=\'/\';\">Go back</button>";
+    let tag = normalize_tags(&tag).into_iter().next().unwrap_or_default();
+
+    let lock = state.pastes.read().unwrap();
+    let mut pastes = lock
+        .iter()
+        .filter(|(_, paste)| is_publicly_listed(paste) && paste.tags.contains(&tag))
+        .collect::<Vec<(&String, &Paste)>>();
+    pastes.sort_by_key(|(_, paste)| Reverse(paste.post_time));
+
+    RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            h2 {"Pastes tagged #" (tag)}
+            @if pastes.is_empty() {
+                p {"There are no public pastes with this tag."}
+            }
+            @for (paste_id, paste) in &pastes {
+                @let view_link = format!("/paste/view/{}", paste_id);
+                p {
+                    a href=(view_link) {(paste.title.clone().unwrap_or_else(|| paste_id.to_string()))}
+                    " posted " (paste.post_time.with_timezone(&Pacific).format("%Y-%m-%d %H:%M:%S %Z"))
+                    @if let Some(language) = &paste.language {
+                        " in " (language)
+                    }
+                    @if let Some(description) = &paste.description {
+                        br;
+                        (description)
+                    }
+                    br;
+                    @for other_tag in &paste.tags {
+                        @let tag_link = format!("/paste/tag/{}", other_tag);
+                        a href=(tag_link) {"#" (other_tag)} " "
+                    }
+                }
+            }
+        }
+        .into_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pages::browse_pastes::{age, browse_pastes, readable_size};
+    use crate::paste::Paste;
+    use crate::state_management::TYRState;
+    use chrono::{Duration, Local};
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+
+    #[test]
+    fn directory_formatting() {
+        assert_eq!(readable_size(10), "10 B");
+        assert_eq!(readable_size(1536), "1.5 KiB");
+        assert_eq!(readable_size(3 * 1048576), "3.0 MiB");
+        assert_eq!(age(Local::now() - Duration::minutes(1)), "1 minute ago");
+        assert_eq!(age(Local::now() - Duration::hours(5)), "5 hours ago");
+        assert_eq!(age(Local::now() - Duration::days(40)), "40 days ago");
+    }
+
+    #[test]
+    fn huge_page_numbers_do_not_overflow() {
+        let state = TYRState::default();
+        state.pastes.write().unwrap().insert(
+            "abc".to_string(),
+            Paste {
+                listed_publicly: true,
+                ..Paste::test_paste("a listed paste")
+            },
+        );
+        let rocket = rocket::build()
+            .manage(state)
+            .mount("/", routes![browse_pastes]);
+        let client = Client::tracked(rocket).unwrap();
+
+        let response = client
+            .get(format!("/paste/browse?page={}", usize::MAX))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let page = response.into_string().unwrap();
+        assert!(page.contains("There are no public pastes to show."));
+        assert!(!page.contains("Next"));
+
+        let page = client
+            .get("/paste/browse")
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(page.contains("/paste/view/abc"));
+    }
+}
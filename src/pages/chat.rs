@@ -0,0 +1,225 @@
+use crate::chat::{
+    close_session, connect_admin, disconnect_admin, is_host_online, open_session, say, ChatEvent,
+    ChatSender, HostMessage,
+};
+use crate::pages::admin::IsAdminGuard;
+use crate::state_management::save_program_state;
+use crate::TYRState;
+use maud::{html, PreEscaped};
+use rocket::futures::{SinkExt, StreamExt};
+use rocket::http::CookieJar;
+use rocket::response::content::RawHtml;
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::State;
+use rocket_ws::{Channel, WebSocket};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+#[get("/chat")]
+/// Page for chatting live with the host, only usable while the host is online.
+pub fn live_chat(state: &State<TYRState>) -> RawHtml<String> {
+    let back_button = "<button onclick=\"window.location.href=\'/\';\">Go back</button>";
+
+    if !is_host_online(state) {
+        return RawHtml(
+            html! {
+                p {"The host is not online right now, feel free to write a message instead!"}
+                (PreEscaped(back_button))
+            }
+            .into_string(),
+        );
+    }
+
+    RawHtml(
+        html! {
+            h1 {"Chat with the host"}
+            p {"This chat is saved as a message once you leave the page."}
+            div id="log" {}
+            input type="text" id="text";
+            button onclick="send()" {"Send"}
+            br;
+            (PreEscaped(back_button))
+            (PreEscaped(r#"
+            <script>
+                const log = document.getElementById("log");
+                const text = document.getElementById("text");
+                const scheme = location.protocol === "https:" ? "wss://" : "ws://";
+                const socket = new WebSocket(scheme + location.host + "/chat/ws");
+                function append(line) {
+                    const p = document.createElement("p");
+                    p.textContent = line;
+                    log.appendChild(p);
+                }
+                socket.onmessage = (event) => {
+                    const line = JSON.parse(event.data);
+                    append(line.from + ": " + line.text);
+                };
+                socket.onclose = () => append("Chat closed.");
+                function send() {
+                    socket.send(text.value);
+                    text.value = "";
+                }
+                text.addEventListener("keydown", (event) => { if (event.key === "Enter") send(); });
+            </script>
+            "#))
+        }
+        .into_string(),
+    )
+}
+
+#[get("/chat/ws")]
+/// Websocket for a visitors chat session, the transcript is saved under their ip and login once they disconnect.
+pub fn chat_socket(
+    ws: WebSocket,
+    req: SocketAddr,
+    jar: &CookieJar,
+    state: &State<TYRState>,
+) -> Option<Channel<'static>> {
+    if !is_host_online(state) {
+        return None;
+    }
+
+    let state = state.inner().clone();
+    let ip = req.ip().to_string();
+    let login = jar.get("login").map(|cookie| cookie.value().to_string());
+
+    Some(ws.channel(move |mut stream| {
+        Box::pin(async move {
+            let (session_id, mut to_visitor) = match open_session(&state, ip, login) {
+                Some(session) => session,
+                None => return Ok(()), // on cooldown or too many chats open, the page shows the chat as closed.
+            };
+            loop {
+                select! {
+                    message = stream.next() => match message {
+                        Some(Ok(rocket_ws::Message::Text(text))) => {
+                            say(&state, &session_id, ChatSender::Visitor, &text)
+                        }
+                        Some(Ok(rocket_ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                        Some(Ok(_)) => {}
+                    },
+                    line = to_visitor.recv() => match line {
+                        Some(line) => {
+                            let text = serde_json::to_string(&line).unwrap();
+                            if stream.send(rocket_ws::Message::Text(text)).await.is_err() {
+                                break;
+                            }
+                        }
+                        None => break,
+                    },
+                }
+            }
+            if close_session(&state, &session_id) {
+                save_program_state((&state).into(), &PathBuf::from("./output/state.ser"));
+            }
+            Ok(())
+        })
+    }))
+}
+
+#[get("/admin/chat")]
+/// Admin only console listing every active chat session, the host is shown as online while this page is open.
+pub fn admin_chat(_is_admin: IsAdminGuard) -> RawHtml<String> {
+    let back_button = "<button onclick=\"window.location.href=\'/admin\';\">Go back</button>";
+
+    RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            h1 {"Live chat"}
+            p {"Visitors see that you are online while this page is open."}
+            div id="sessions" {}
+            (PreEscaped(r#"
+            <script>
+                const sessions = document.getElementById("sessions");
+                const scheme = location.protocol === "https:" ? "wss://" : "ws://";
+                const socket = new WebSocket(scheme + location.host + "/admin/chat/ws");
+                function append(log, line) {
+                    const p = document.createElement("p");
+                    p.textContent = line;
+                    log.appendChild(p);
+                }
+                socket.onmessage = (event) => {
+                    const chat_event = JSON.parse(event.data);
+                    if (chat_event.type === "opened") {
+                        const div = document.createElement("div");
+                        div.id = chat_event.session_id;
+                        const title = document.createElement("h3");
+                        title.textContent = "Visitor " + chat_event.ip;
+                        const log = document.createElement("div");
+                        log.className = "log";
+                        const text = document.createElement("input");
+                        const button = document.createElement("button");
+                        button.textContent = "Send";
+                        button.onclick = () => {
+                            socket.send(JSON.stringify({ session_id: chat_event.session_id, text: text.value }));
+                            text.value = "";
+                        };
+                        div.append(title, log, text, button);
+                        sessions.appendChild(div);
+                    } else if (chat_event.type === "line") {
+                        const div = document.getElementById(chat_event.session_id);
+                        if (div) append(div.querySelector(".log"), chat_event.line.from + ": " + chat_event.line.text);
+                    } else if (chat_event.type === "closed") {
+                        const div = document.getElementById(chat_event.session_id);
+                        if (div) append(div.querySelector(".log"), "Visitor left, transcript saved.");
+                    }
+                };
+                socket.onclose = () => append(sessions, "Console disconnected, reload the page to go back online.");
+            </script>
+            "#))
+        }
+        .into_string(),
+    )
+}
+
+#[get("/admin/chat/ws")]
+/// Websocket for an admin console, receives every chat event and sends the hosts replies.
+pub fn admin_chat_socket(
+    ws: WebSocket,
+    _is_admin: IsAdminGuard,
+    state: &State<TYRState>,
+) -> Channel<'static> {
+    let state = state.inner().clone();
+
+    ws.channel(move |mut stream| {
+        Box::pin(async move {
+            let (catch_up, mut events) = connect_admin(&state);
+
+            for event in catch_up {
+                let text = serde_json::to_string(&event).unwrap();
+                if stream.send(rocket_ws::Message::Text(text)).await.is_err() {
+                    disconnect_admin(&state);
+                    return Ok(());
+                }
+            }
+
+            loop {
+                select! {
+                    message = stream.next() => match message {
+                        Some(Ok(rocket_ws::Message::Text(text))) => {
+                            if let Ok(host_message) = serde_json::from_str::<HostMessage>(&text) {
+                                say(&state, &host_message.session_id, ChatSender::Host, &host_message.text);
+                            }
+                        }
+                        Some(Ok(rocket_ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                        Some(Ok(_)) => {}
+                    },
+                    event = events.recv() => match event {
+                        Ok(event) => {
+                            let text = serde_json::to_string::<ChatEvent>(&event).unwrap();
+                            if stream.send(rocket_ws::Message::Text(text)).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(RecvError::Lagged(_)) => {}
+                        Err(RecvError::Closed) => break,
+                    },
+                }
+            }
+
+            disconnect_admin(&state);
+            Ok(())
+        })
+    })
+}
@@ -0,0 +1,249 @@
+use crate::diff::{render_diff, unified_patch, DiffMode};
+use crate::pages::outcome_pages::paste_404;
+use crate::pages::post_paste::paste_access_denied;
+use crate::state_management::TYRState;
+use maud::{html, PreEscaped};
+use rocket::http::{CookieJar, Status};
+use rocket::response::content::RawHtml;
+use rocket::State;
+use rocket_download_response::DownloadResponse;
+
+/// Returns the current text of a paste to compare, or the page to show instead if it can not be compared.
+/// Pastes with limited views are left out, as comparing them would reveal their contents without using up a view.
+fn comparable_text(
+    state: &State<TYRState>,
+    jar: &CookieJar,
+    paste_id: &str,
+) -> Result<String, RawHtml<String>> {
+    if let Some(denied_page) = paste_access_denied(state, jar, paste_id) {
+        return Err(denied_page);
+    }
+
+    let back_button = "<button onclick=\"window.location.href=\'/paste/diff\';\">Go back</button>";
+    let message = match state.pastes.read().unwrap().get(paste_id) {
+        None => paste_404(),
+        Some(paste) if paste.max_views.is_some() => {
+            "Pastes with limited views can not be compared.".to_string()
+        }
+        Some(paste) if paste.client_encrypted => {
+            "Encrypted pastes can not be compared.".to_string()
+        }
+        Some(paste) => match paste.current_text() {
+            Some(text) => return Ok(text),
+            None => "Only text pastes can be compared.".to_string(),
+        },
+    };
+    Err(RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            p {(paste_id) ": " (message)}
+        }
+        .into_string(),
+    ))
+}
+
+#[get("/paste/diff?<a>&<b>&<mode>")]
+/// Page comparing two pastes line by line, e.g. /paste/diff?a=<paste_id>&b=<paste_id>
+/// Shows a form to choose the pastes when either is missing.
+pub fn compare_pastes(
+    a: Option<String>,
+    b: Option<String>,
+    mode: Option<DiffMode>,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> RawHtml<String> {
+    let back_button = "<button onclick=\"window.location.href=\'/\';\">Go back</button>";
+    let (a, b) = match (a, b) {
+        (Some(a), Some(b)) if !a.trim().is_empty() && !b.trim().is_empty() => {
+            (a.trim().to_string(), b.trim().to_string())
+        }
+        (a, b) => {
+            return RawHtml(
+                html! {
+                    (PreEscaped(back_button))
+                    h2 {"Compare pastes"}
+                    form action="/paste/diff" method="get" {
+                        label for="a" {"Before: "}
+                        input type="text" name="a" id="a" placeholder="paste id" value=(a.unwrap_or_default());
+                        br;
+                        label for="b" {"After: "}
+                        input type="text" name="b" id="b" placeholder="paste id" value=(b.unwrap_or_default());
+                        br;
+                        label for="mode" {"Mode: "}
+                        select name="mode" id="mode" {
+                            option value="Unified" {"Unified"}
+                            option value="SideBySide" {"Side by side"}
+                        }
+                        br;
+                        input type="submit" value="Compare";
+                    }
+                }
+                .into_string(),
+            )
+        }
+    };
+
+    let old = match comparable_text(state, jar, &a) {
+        Ok(text) => text,
+        Err(page) => return page,
+    };
+    let new = match comparable_text(state, jar, &b) {
+        Ok(text) => text,
+        Err(page) => return page,
+    };
+    let mode = mode.unwrap_or_default();
+
+    let diff_url = format!("/paste/diff?a={}&b={}", a, b);
+    let unified_url = format!("{}&mode=Unified", diff_url);
+    let side_by_side_url = format!("{}&mode=SideBySide", diff_url);
+    let swapped_url = format!("/paste/diff?a={}&b={}&mode={:?}", b, a, mode);
+    let patch_url = format!("/paste/diff/patch?a={}&b={}", a, b);
+    let old_link = format!("/paste/view/{}", a);
+    let new_link = format!("/paste/view/{}", b);
+
+    RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            p {"Changes from " a href=(old_link) {(a)} " to " a href=(new_link) {(b)} ":"}
+            @match mode {
+                DiffMode::Unified => {
+                    a href=(side_by_side_url) {"View side by side"}
+                }
+                DiffMode::SideBySide => {
+                    a href=(unified_url) {"View unified"}
+                }
+            }
+            " "
+            a href=(swapped_url) {"Swap"}
+            " "
+            a href=(patch_url) {"Download patch"}
+            br;
+            br;
+            (render_diff(&old, &new, mode))
+        }
+        .into_string(),
+    )
+}
+
+#[get("/paste/diff/patch?<a>&<b>")]
+/// Downloads the differences between two pastes as a .patch file, in the unified format used by patch and git apply.
+pub fn compare_pastes_patch(
+    a: String,
+    b: String,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> Result<DownloadResponse, Status> {
+    {
+        let lock = state.pastes.read().unwrap();
+        if !lock.contains_key(&a) || !lock.contains_key(&b) {
+            return Err(Status::NotFound);
+        }
+    }
+    let old = comparable_text(state, jar, &a).map_err(|_| Status::Forbidden)?;
+    let new = comparable_text(state, jar, &b).map_err(|_| Status::Forbidden)?;
+    let patch = unified_patch(&old, &new, &format!("a/{}", a), &format!("b/{}", b));
+
+    Ok(DownloadResponse::from_vec(
+        patch.into_bytes(),
+        Some(format!("{}-{}.patch", a, b)),
+        None,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pages::compare_pastes::{compare_pastes, compare_pastes_patch};
+    use crate::paste::{Paste, PasteVisibility};
+    use crate::state_management::TYRState;
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+
+    #[test]
+    fn comparing_pastes() {
+        let state = TYRState::default();
+        let mut protected = Paste::test_paste("protected secret\n");
+        protected.set_password("hunter2").unwrap();
+        let pastes = [
+            ("old", Paste::test_paste("one\nsame\n")),
+            ("new", Paste::test_paste("two\nsame\n")),
+            (
+                "private",
+                Paste {
+                    visibility: PasteVisibility::PrivateToLogin,
+                    login_cookie_of_poster: Some("owner".to_string()),
+                    ..Paste::test_paste("private secret\n")
+                },
+            ),
+            ("protected", protected),
+            (
+                "limited",
+                Paste {
+                    max_views: Some(1),
+                    ..Paste::test_paste("limited secret\n")
+                },
+            ),
+            (
+                "encrypted",
+                Paste {
+                    client_encrypted: true,
+                    ..Paste::test_paste("encrypted secret\n")
+                },
+            ),
+        ];
+        for (paste_id, paste) in pastes {
+            state
+                .pastes
+                .write()
+                .unwrap()
+                .insert(paste_id.to_string(), paste);
+        }
+        let rocket = rocket::build()
+            .manage(state.clone())
+            .mount("/", routes![compare_pastes, compare_pastes_patch]);
+        let client = Client::tracked(rocket).unwrap();
+        let page = |uri: &str| client.get(uri).dispatch().into_string().unwrap();
+
+        let unified = page("/paste/diff?a=old&b=new");
+        assert!(unified.contains("View side by side"));
+        assert!(unified.contains("-one"));
+        assert!(unified.contains("+two"));
+
+        let side_by_side = page("/paste/diff?a=old&b=new&mode=SideBySide");
+        assert!(side_by_side.contains("View unified"));
+        assert!(side_by_side.contains("<pre>one</pre>"));
+        assert!(side_by_side.contains("<pre>two</pre>"));
+
+        let response = client.get("/paste/diff/patch?a=old&b=new").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response
+            .headers()
+            .get_one("Content-Disposition")
+            .unwrap()
+            .contains("old-new.patch"));
+        let patch = response.into_string().unwrap();
+        assert!(patch.contains("--- a/old"));
+        assert!(patch.contains("+++ b/new"));
+        assert!(patch.contains("-one\n+two\n"));
+
+        for refused in ["private", "protected", "limited", "encrypted"] {
+            let refused_page = page(&format!("/paste/diff?a=old&b={}", refused));
+            assert!(!refused_page.contains("secret"), "{refused} was compared");
+            let response = client
+                .get(format!("/paste/diff/patch?a={}&b=new", refused))
+                .dispatch();
+            assert_eq!(
+                response.status(),
+                Status::Forbidden,
+                "{refused} was patched"
+            );
+        }
+        assert_eq!(state.pastes.read().unwrap()["limited"].view_count.get(), 0);
+        assert_eq!(
+            client
+                .get("/paste/diff/patch?a=old&b=missing")
+                .dispatch()
+                .status(),
+            Status::NotFound
+        );
+    }
+}
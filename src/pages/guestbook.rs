@@ -0,0 +1,156 @@
+use crate::message::{GuestbookStatus, Message};
+use crate::{TYRState, GUESTBOOK_PAGE_SIZE};
+use chrono_tz::US::Pacific;
+use maud::{html, PreEscaped, DOCTYPE};
+use rocket::response::content::RawHtml;
+use rocket::State;
+use std::cmp::Reverse;
+
+#[get("/guestbook?<page>")]
+/// Public page listing every message the host approved for the guestbook, newest first and paginated.
+pub fn guestbook(page: Option<usize>, state: &State<TYRState>) -> RawHtml<String> {
+    let page = page.unwrap_or_default();
+
+    let mut approved: Vec<Message> = state
+        .messages
+        .read()
+        .unwrap()
+        .values()
+        .flat_map(|user| user.messages.iter())
+        .filter(|message| {
+            message
+                .guestbook
+                .as_ref()
+                .map(|entry| entry.status == GuestbookStatus::Approved)
+                .unwrap_or(false)
+        })
+        .cloned()
+        .collect();
+
+    approved.sort_by_key(|message| Reverse(message.time_stamp));
+
+    let page_count = approved.len().div_ceil(GUESTBOOK_PAGE_SIZE);
+
+    let entries = approved
+        .into_iter()
+        .skip(page.saturating_mul(GUESTBOOK_PAGE_SIZE))
+        .take(GUESTBOOK_PAGE_SIZE)
+        .map(|message| {
+            let display_name = message
+                .guestbook
+                .and_then(|entry| entry.display_name)
+                .unwrap_or("Anonymous".to_string());
+            (
+                display_name,
+                message.time_stamp.with_timezone(&Pacific).date_naive(),
+                message.text,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let newer_page = format!("/guestbook?page={}", page.saturating_sub(1));
+    let older_page = format!("/guestbook?page={}", page.saturating_add(1));
+
+    let back_button = "<button onclick=\"window.location.href=\'/\';\">Go back</button>";
+
+    RawHtml(
+        html! {
+            (DOCTYPE)
+            title {"Guestbook"}
+            h1 {"Guestbook"}
+            (PreEscaped(back_button))
+            @if entries.is_empty() {
+                p {"Nobody has signed the guestbook yet."}
+            }
+            @for (display_name, date, text) in &entries {
+                p {
+                    b { (display_name) } " (" (date) "): "
+                    (text)
+                }
+            }
+            @if page > 0 {
+                a href=(newer_page) {"Newer"}
+                " "
+            }
+            @if page.saturating_add(1) < page_count {
+                a href=(older_page) {"Older"}
+            }
+        }
+        .into_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::{GuestbookEntry, GuestbookStatus, Message, NewMessage};
+    use crate::pages::admin::guestbook_queue;
+    use crate::pages::guestbook::guestbook;
+    use crate::state_management::TYRState;
+    use crate::user::User;
+    use rocket::http::{Cookie, Status};
+    use rocket::local::blocking::Client;
+
+    /// Creates a message the way a submitted form does, opting in to the guestbook only if public is ticked.
+    fn submitted_message(text: &str, public: bool) -> Message {
+        let new_message = NewMessage {
+            msg: text.to_string(),
+            public,
+            display_name: Some("<b>name</b>".to_string()),
+        };
+        let entry = GuestbookEntry::from_new_message(&new_message);
+        Message::new(text.to_string(), None, entry, None).unwrap()
+    }
+
+    #[test]
+    fn only_approved_opted_in_messages_are_shown() {
+        let state = TYRState::default();
+        state
+            .admin_state
+            .write()
+            .unwrap()
+            .admin_hashes
+            .push("admin".to_string());
+
+        let mut approved = submitted_message("<script>alert(1)</script>", true);
+        approved.guestbook.as_mut().unwrap().status = GuestbookStatus::Approved;
+        let mut user = User::new(submitted_message("a private message", false));
+        user.push(submitted_message("a pending message", true));
+        user.push(approved);
+        state
+            .messages
+            .write()
+            .unwrap()
+            .insert("1.2.3.4".to_string(), user);
+
+        let rocket = rocket::build()
+            .manage(state)
+            .mount("/", routes![guestbook, guestbook_queue]);
+        let client = Client::tracked(rocket).unwrap();
+
+        let queue = client
+            .get("/admin/guestbook")
+            .cookie(Cookie::new("login", "admin"))
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(queue.contains("a pending message"));
+        assert!(!queue.contains("a private message"));
+
+        let response = client.get("/guestbook").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let page = response.into_string().unwrap();
+        assert!(!page.contains("a private message"));
+        assert!(!page.contains("a pending message"));
+        assert!(page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(page.contains("&lt;b&gt;name&lt;/b&gt;"));
+        assert!(!page.contains("<script>alert(1)"));
+
+        let page = client
+            .get(format!("/guestbook?page={}", usize::MAX))
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(page.contains("Nobody has signed the guestbook yet."));
+        assert!(!page.contains("Older"));
+    }
+}
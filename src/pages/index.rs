@@ -1,3 +1,4 @@
+use crate::chat::is_host_online;
 use crate::pages::login::login;
 use crate::verified_guard::GetVerifiedGuard;
 use crate::VERSION;
@@ -92,6 +93,10 @@ pub fn index(
         p {"Feel free to write a message if anything I have made was interesting to you, or if I helped in any sort of way. :)"}
         p {(online_user_text)}
 
+        @if is_host_online(state) {
+            p."host-online" {"The host is online! " a href="/chat" {"Chat live with the host"}}
+        }
+
         @if !is_logged_in {
             a href="/login" {"login"}
         }
@@ -119,6 +124,14 @@ pub fn index(
         br;
         (PreEscaped("<button onclick=\"window.location.href=\'/paste/new\';\">Create paste</button>"))
         br;
+        (PreEscaped("<button onclick=\"window.location.href=\'/paste/mine\';\">My pastes</button>"))
+        br;
+        (PreEscaped("<button onclick=\"window.location.href=\'/paste/browse\';\">Browse public pastes</button>"))
+        br;
+        (PreEscaped("<button onclick=\"window.location.href=\'/paste/diff\';\">Compare pastes</button>"))
+        br;
+        (PreEscaped("<button onclick=\"window.location.href=\'/guestbook\';\">Guestbook</button>"))
+        br;
         h3 {"Browser Capable Projects:"}
         a href="/rhythm_rs" {"Rhythm Rs"}
         br;
@@ -4,11 +4,12 @@ use argon2::password_hash::SaltString;
 use argon2::{Argon2, PasswordHasher};
 use lazy_static::lazy_static;
 use rocket::form::Form;
-use rocket::http::{Cookie, CookieJar, SameSite};
+use rocket::http::{Cookie, CookieJar, SameSite, Status};
 use rocket::request::{FromRequest, Outcome};
 use rocket::response::content::RawHtml;
 use rocket::response::Redirect;
 use rocket::{Request, State};
+use sha2::{Digest, Sha256};
 use std::fs::File;
 use std::io::{Read, Write};
 use std::net::SocketAddr;
@@ -33,6 +34,17 @@ lazy_static! {
     };
 }
 
+/// The number of hex characters of a login id.
+pub static LOGIN_ID_LENGTH: usize = 16;
+
+/// Derives a login id from the hash stored in a login cookie.
+/// Unlike the hash, the id can be shared without letting others log in as that user, so it is used to list who may read a private paste.
+pub fn login_id(login_hash: &str) -> String {
+    let mut id = hex::encode(Sha256::digest(login_hash.as_bytes()));
+    id.truncate(LOGIN_ID_LENGTH);
+    id
+}
+
 #[get("/login")]
 /// Login route containing buttons to submit a form with a password.
 pub fn login() -> RawHtml<String> {
@@ -92,9 +104,9 @@ pub fn login_post(
         .hash_password(password.password.as_bytes(), salt.as_str())
         .unwrap();
 
-    let cookie =
-        Cookie::build("login", hash_password.hash.unwrap().to_string()).same_site(SameSite::Strict);
-    jar.add(cookie.finish());
+    let cookie = Cookie::build(("login", hash_password.hash.unwrap().to_string()))
+        .same_site(SameSite::Strict);
+    jar.add(cookie);
 
     let ip = &req.ip().to_string();
     match state.unique_users.write().unwrap().get_mut(ip) {
@@ -135,7 +147,7 @@ impl<'r> FromRequest<'r> for IsLoggedInGuard {
         if let Some(login_cookie) = req.cookies().get("login") {
             Outcome::Success(Self(login_cookie.value().to_string()))
         } else {
-            Outcome::Forward(())
+            Outcome::Forward(Status::Unauthorized)
         }
     }
 }
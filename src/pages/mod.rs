@@ -1,10 +1,17 @@
 // module for all of the pages that get used for this project
 pub mod admin;
+pub mod api; // the json api for pastes, and the page for managing api tokens
+pub mod browse_pastes; // the public paste directory, and pastes with a tag
+pub mod chat; // live chat between visitors and the host
+pub mod compare_pastes; // diffing two different pastes against each other
 pub mod error_catch_pages; // pages relating to error catching
+pub mod guestbook; // the public page of host approved messages
 pub mod index; // the base page of the project
 pub mod login;
+pub mod my_pastes; // the dashboard of pastes created by the current login or ip
 pub mod new; // the page for creating new messages through a form
 pub mod outcome_pages; // module of pages for getting specific info to the user e.g. "message was too long" or "message contained an error" or otherwise
+pub mod paste_revisions; // editing pastes, and viewing the history and diffs of edited pastes
 pub mod post_paste;
 pub mod submit_message; // the post request handler for handling a new message from the new message page
 pub mod view; // a page for viewing all the messages sent by a user
@@ -0,0 +1,98 @@
+use crate::pages::post_paste::is_paste_owner;
+use crate::state_management::TYRState;
+use chrono_tz::US::Pacific;
+use maud::{html, PreEscaped};
+use rocket::http::CookieJar;
+use rocket::response::content::RawHtml;
+use rocket::State;
+use std::cmp::Reverse;
+use std::net::SocketAddr;
+
+#[get("/paste/mine")]
+/// Page listing the pastes created by the current login, or by the current ip when not logged in, newest first.
+pub fn my_pastes(req: SocketAddr, state: &State<TYRState>, jar: &CookieJar) -> RawHtml<String> {
+    let back_button = "<button onclick=\"window.location.href=\'/\';\">Go back</button>";
+    let api_tokens_button =
+        "<button onclick=\"window.location.href=\'/paste/api_tokens\';\">Api tokens</button>";
+    let viewer_login = jar.get("login").map(|cookie| cookie.value().to_string());
+    let ip = req.ip().to_string();
+    let unique_visitors = state.counter_config.read().unwrap().unique_visitors;
+
+    let mut pastes = {
+        let lock = state.pastes.read().unwrap();
+        lock.iter()
+            .filter(|(_, paste)| {
+                paste.is_posted_by(viewer_login.as_deref(), &ip) && !paste.is_expired()
+            })
+            .map(|(paste_id, paste)| {
+                (
+                    paste_id.clone(),
+                    paste.post_time,
+                    paste.displayed_views(unique_visitors),
+                    paste.download_count.get(),
+                    paste.expires_at,
+                    paste.views_remaining(),
+                    is_paste_owner(state, jar, paste, None),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    pastes.sort_by_key(|paste| Reverse(paste.1));
+
+    RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            @if viewer_login.is_some() {
+                (PreEscaped(api_tokens_button))
+            }
+            @match viewer_login {
+                Some(_) => p {"Pastes created while logged in with your current login:"},
+                None => p {"Pastes created from your ip while not logged in. Log in to manage your pastes from anywhere, pastes without a login can only be deleted with their deletion token."},
+            }
+            @if pastes.is_empty() {
+                p {"You have not created any pastes."}
+            } @else {
+                table {
+                    tr {
+                        th {"Paste"}
+                        th {"Posted"}
+                        th {"Views"}
+                        th {"Downloads"}
+                        th {"Expires"}
+                        th {"Actions"}
+                    }
+                    @for (paste_id, post_time, view_count, download_count, expires_at, views_remaining, can_manage) in &pastes {
+                        @let view_link = format!("/paste/view/{}", paste_id);
+                        @let edit_link = format!("/paste/edit/{}", paste_id);
+                        @let delete_url = format!("/paste/delete/{}", paste_id);
+                        tr {
+                            td { a href=(view_link) {(paste_id)} }
+                            td {(post_time.with_timezone(&Pacific).format("%Y-%m-%d %H:%M:%S %Z"))}
+                            td {(view_count)}
+                            td {(download_count)}
+                            td {
+                                @match expires_at {
+                                    Some(expires_at) => (expires_at.with_timezone(&Pacific).format("%Y-%m-%d %H:%M:%S %Z")),
+                                    None => "Never",
+                                }
+                                @if let Some(views_remaining) = views_remaining {
+                                    " or after " (views_remaining) " more views"
+                                }
+                            }
+                            td {
+                                @if *can_manage {
+                                    a href=(edit_link) {"Edit"}
+                                    " "
+                                    form action=(delete_url) method="post" style="display:inline" {
+                                        input type="submit" value="Delete";
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        .into_string(),
+    )
+}
@@ -20,6 +20,13 @@ pub fn new(_req: SocketAddr, _messages: &State<TYRState>) -> RawHtml<String> {
                 <label for="msg">Enter message</label>
                 <br>
                 <input type="text" name="msg" id="msg">
+                <br>
+                <input type="checkbox" name="public" id="public" value="true">
+                <label for="public">Allow this to be shown publicly on the guestbook</label>
+                <br>
+                <label for="display_name">Display name (optional)</label>
+                <input type="text" name="display_name" id="display_name">
+                <br>
                 <input type="submit" value="Submit Message">
             </form>
         </body>
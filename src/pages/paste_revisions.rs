@@ -0,0 +1,305 @@
+use crate::diff::{render_diff, unified_patch, DiffMode};
+use crate::pages::post_paste::{is_paste_owner, paste_access_denied};
+use crate::state_management::TYRState;
+use crate::verified_guard::GetVerifiedGuard;
+use crate::{PASTE_LENGTH_CAP, PASTE_LENGTH_MIN};
+use chrono_tz::US::Pacific;
+use maud::html;
+use rocket::form::Form;
+use rocket::http::{ContentType, CookieJar, Status};
+use rocket::response::content::RawHtml;
+use rocket::response::Redirect;
+use rocket::State;
+
+#[derive(FromForm, Debug, Clone)]
+/// Form struct for the new contents of an edited paste.
+pub struct EditPaste {
+    pub text: String,
+}
+
+/// Returns the url of a route that takes the paste owners deletion token, keeping the token if one was given.
+fn with_token(url: String, token: &Option<String>) -> String {
+    match token {
+        None => url,
+        Some(token) => format!("{}?token={}", url, token),
+    }
+}
+
+/// Returns the page to show instead of the revisions of a paste, if its revisions can not be viewed.
+/// Pastes with limited views are left out, as every revision would otherwise be readable without using up a view.
+fn revisions_denied(
+    state: &State<TYRState>,
+    jar: &CookieJar,
+    paste_id: &str,
+) -> Option<RawHtml<String>> {
+    if let Some(denied_page) = paste_access_denied(state, jar, paste_id) {
+        return Some(denied_page);
+    }
+    let is_view_limited = state
+        .pastes
+        .read()
+        .unwrap()
+        .get(paste_id)
+        .map(|paste| paste.max_views.is_some())
+        .unwrap_or(false);
+    is_view_limited.then(|| {
+        let back_link = format!("/paste/view/{}", paste_id);
+        RawHtml(
+            html! {
+                a href=(back_link) {"Go back"}
+                p {"Pastes with limited views have no viewable history."}
+            }
+            .into_string(),
+        )
+    })
+}
+
+#[get("/paste/edit/<paste_id>?<token>")]
+/// Page for the owner of a paste to edit it, the previous contents are kept as a revision.
+pub fn edit_paste(
+    paste_id: String,
+    token: Option<String>,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> Result<RawHtml<String>, Status> {
+    let text = {
+        let lock = state.pastes.read().unwrap();
+        let paste = lock.get(&paste_id).ok_or(Status::NotFound)?;
+        if !is_paste_owner(state, jar, paste, token.as_deref()) {
+            return Err(Status::Forbidden);
+        }
+        paste.current_text().ok_or(Status::UnprocessableEntity)?
+    };
+
+    let back_link = format!("/paste/view/{}", paste_id);
+    let edit_url = with_token(format!("/paste/edit/{}", paste_id), &token);
+
+    Ok(RawHtml(
+        html! {
+            a href=(back_link) {"Go back"}
+            form action=(edit_url) method="post" {
+                label for="text" {"Edit paste"}
+                br;
+                textarea rows="20" cols="80" name="text" id="text" {(text)}
+                br;
+                input type="submit" value="Save edit";
+            }
+        }
+        .into_string(),
+    ))
+}
+
+#[post("/paste/edit/<paste_id>?<token>", data = "<edit>")]
+/// Route for saving an edit to a paste, requires being the owner of the paste.
+pub fn edit_paste_post(
+    paste_id: String,
+    token: Option<String>,
+    edit: Form<EditPaste>,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+    is_verified: GetVerifiedGuard,
+) -> Result<Redirect, Status> {
+    // require unverified users edits to meet the same requirements of length as a new paste
+    if !is_verified.0 && (edit.text.len() > PASTE_LENGTH_CAP || edit.text.len() < PASTE_LENGTH_MIN)
+    {
+        return Ok(Redirect::to(uri!("/error_message")));
+    }
+
+    let mut lock = state.pastes.write().unwrap();
+    let paste = lock.get_mut(&paste_id).ok_or(Status::NotFound)?;
+    if !is_paste_owner(state, jar, paste, token.as_deref()) {
+        return Err(Status::Forbidden);
+    }
+    match paste.edit(edit.text.clone()) {
+        Ok(_) => Ok(Redirect::to(uri!(crate::pages::post_paste::view_paste(
+            paste_id,
+            _
+        )))),
+        Err(err) => {
+            println!("Unable to edit paste {paste_id}. {err}");
+            Ok(Redirect::to(uri!("/error_message")))
+        }
+    }
+}
+
+#[get("/paste/view/<paste_id>/history")]
+/// Page listing every revision of a paste, with links to diffs between them.
+pub fn paste_history(
+    paste_id: String,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> Result<RawHtml<String>, Status> {
+    if let Some(denied_page) = revisions_denied(state, jar, &paste_id) {
+        return Ok(denied_page);
+    }
+
+    let revisions = {
+        let lock = state.pastes.read().unwrap();
+        let paste = lock.get(&paste_id).ok_or(Status::NotFound)?;
+        (0..paste.revision_count())
+            .filter_map(|revision| {
+                paste
+                    .revision_time(revision)
+                    .map(|time| (revision, time.with_timezone(&Pacific)))
+            })
+            .collect::<Vec<_>>()
+    };
+    let current = revisions.len() - 1;
+
+    let back_link = format!("/paste/view/{}", paste_id);
+
+    Ok(RawHtml(
+        html! {
+            a href=(back_link) {"Go back"}
+            p {"Revisions of this paste, newest first:"}
+            @for (revision, time) in revisions.iter().rev() {
+                @let previous = revision.saturating_sub(1);
+                @let diff_previous = format!("/paste/view/{}/diff/{}/{}", paste_id, previous, revision);
+                @let diff_current = format!("/paste/view/{}/diff/{}/{}", paste_id, revision, current);
+                "Revision " (revision) " posted " (time.format("%Y-%m-%d %H:%M:%S %Z"))
+                @if *revision == current {
+                    " (current)"
+                }
+                @if *revision > 0 {
+                    " " a href=(diff_previous) {"diff with previous"}
+                }
+                @if *revision < current {
+                    " " a href=(diff_current) {"diff with current"}
+                }
+                br;
+            }
+        }
+        .into_string(),
+    ))
+}
+
+#[get("/paste/view/<paste_id>/diff/<a>/<b>?<mode>")]
+/// Page showing the differences between two revisions of a paste, either unified or side by side.
+pub fn paste_diff(
+    paste_id: String,
+    a: usize,
+    b: usize,
+    mode: Option<DiffMode>,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> Result<RawHtml<String>, Status> {
+    if let Some(denied_page) = revisions_denied(state, jar, &paste_id) {
+        return Ok(denied_page);
+    }
+
+    let (old, new) = {
+        let lock = state.pastes.read().unwrap();
+        let paste = lock.get(&paste_id).ok_or(Status::NotFound)?;
+        (
+            paste.revision_text(a).ok_or(Status::NotFound)?,
+            paste.revision_text(b).ok_or(Status::NotFound)?,
+        )
+    };
+    let mode = mode.unwrap_or_default();
+
+    let back_link = format!("/paste/view/{}/history", paste_id);
+    let diff_url = format!("/paste/view/{}/diff/{}/{}", paste_id, a, b);
+    let unified_url = format!("{}?mode=Unified", diff_url);
+    let side_by_side_url = format!("{}?mode=SideBySide", diff_url);
+    let patch_url = format!("{}/patch", diff_url);
+
+    Ok(RawHtml(
+        html! {
+            a href=(back_link) {"Go back"}
+            p {"Changes from revision " (a) " to revision " (b) ":"}
+            @match mode {
+                DiffMode::Unified => {
+                    a href=(side_by_side_url) {"View side by side"}
+                }
+                DiffMode::SideBySide => {
+                    a href=(unified_url) {"View unified"}
+                }
+            }
+            " "
+            a href=(patch_url) {"Download patch"}
+            br;
+            br;
+            (render_diff(&old, &new, mode))
+        }
+        .into_string(),
+    ))
+}
+
+#[get("/paste/view/<paste_id>/diff/<a>/<b>/patch")]
+/// Returns the differences between two revisions of a paste as a unified diff that can be applied with patch.
+pub fn paste_diff_patch(
+    paste_id: String,
+    a: usize,
+    b: usize,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> Result<(ContentType, String), Status> {
+    if revisions_denied(state, jar, &paste_id).is_some() {
+        return Err(Status::Forbidden);
+    }
+
+    let lock = state.pastes.read().unwrap();
+    let paste = lock.get(&paste_id).ok_or(Status::NotFound)?;
+    let old = paste.revision_text(a).ok_or(Status::NotFound)?;
+    let new = paste.revision_text(b).ok_or(Status::NotFound)?;
+    Ok((
+        ContentType::Plain,
+        unified_patch(
+            &old,
+            &new,
+            &format!("a/{}", paste_id),
+            &format!("b/{}", paste_id),
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pages::paste_revisions::{paste_diff, paste_diff_patch, paste_history};
+    use crate::paste::Paste;
+    use crate::state_management::TYRState;
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+
+    #[test]
+    fn view_limited_revisions_are_refused() {
+        let state = TYRState::default();
+        let mut limited = Paste {
+            max_views: Some(1),
+            ..Paste::test_paste("the original secret")
+        };
+        limited.edit("the edited secret".to_string()).unwrap();
+        let mut unlimited = Paste::test_paste("one");
+        unlimited.edit("two".to_string()).unwrap();
+        {
+            let mut pastes = state.pastes.write().unwrap();
+            pastes.insert("limited".to_string(), limited);
+            pastes.insert("unlimited".to_string(), unlimited);
+        }
+        let pastes = state.pastes.clone();
+        let rocket = rocket::build()
+            .manage(state)
+            .mount("/", routes![paste_history, paste_diff, paste_diff_patch]);
+        let client = Client::tracked(rocket).unwrap();
+
+        for uri in [
+            "/paste/view/limited/history",
+            "/paste/view/limited/diff/0/1",
+        ] {
+            let page = client.get(uri).dispatch().into_string().unwrap();
+            assert!(page.contains("Pastes with limited views have no viewable history."));
+            assert!(!page.contains("secret"));
+        }
+        let response = client.get("/paste/view/limited/diff/0/1/patch").dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert_eq!(pastes.read().unwrap()["limited"].view_count.get(), 0);
+
+        let patch = client
+            .get("/paste/view/unlimited/diff/0/1/patch")
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(patch.contains("-one"));
+        assert!(patch.contains("+two"));
+    }
+}
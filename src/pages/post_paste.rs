@@ -1,80 +1,198 @@
 use crate::pages::admin::check_is_admin;
+use crate::pages::login::login_id;
 use crate::pages::outcome_pages::paste_404;
-use crate::paste::{Paste, PasteContents};
+use crate::paste::{
+    forks_of, insert_new_paste, short_link_url, Paste, PasteContents, PasteExpiry, PasteOptions,
+    PasteVisibility,
+};
+use crate::paste_access::{grant_access, has_access, try_paste_password};
+use crate::paste_cleanup::delete_paste;
+use crate::paste_counter::visitor_id;
+use crate::paste_encryption::{
+    encrypt_in_browser_input, is_valid_ciphertext, plaintext_length, render_decryption_page,
+};
+use crate::plain_text_guard::WantsPlainTextGuard;
+use crate::render::{render_paste, RenderMode};
 use crate::verified_guard::{GetVerifiedGuard, RequireVerifiedGuard};
+use crate::webhook::{queue_webhook, WebhookEvent};
 use crate::{TYRState, PASTE_LENGTH_CAP, PASTE_LENGTH_MIN};
 use chrono::{Datelike, Local, Timelike};
-use maud::{html, PreEscaped};
+use maud::{html, Markup, PreEscaped};
 use rocket::data::ToByteUnit;
 use rocket::form::Form;
 use rocket::http::{ContentType, CookieJar, Status};
+use rocket::request::FlashMessage;
 use rocket::response::content::RawHtml;
-use rocket::response::Redirect;
+use rocket::response::{Flash, Redirect, Responder};
 use rocket::tokio::io::AsyncReadExt;
-use rocket::{Data, State};
+use rocket::{Data, Either, Request, Response, State};
 use rocket_download_response::DownloadResponse;
 use rocket_multipart_form_data::{
     MultipartFormData, MultipartFormDataField, MultipartFormDataOptions,
 };
-use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
-use std::hash::{Hash, Hasher};
-use std::io::{ErrorKind, Read, Write};
-use std::net::SocketAddr;
+use std::io::{Cursor, ErrorKind, Write};
+use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
 
+/// The kind of the flash message holding the deletion token of a newly created paste.
+pub static DELETION_TOKEN_FLASH: &str = "deletion_token";
+
 #[derive(FromForm, Debug, Clone)]
 /// Form struct for a message
 pub struct NewPaste {
     pub text: String,
     pub custom_url: Option<String>,
+    pub expiry: Option<PasteExpiry>,
+    pub max_views: Option<u32>,
+    pub burn_after_reading: bool,
+    pub language: Option<String>,
+    pub visibility: Option<PasteVisibility>,
+    pub allowed_logins: Option<String>,
+    pub password: Option<String>,
+    pub forked_from: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub tags: Option<String>,
+    pub list_publicly: bool,
+    pub short_link: bool, // the text is a url the paste redirects to, verified users only
+    pub client_encrypted: bool, // the text is ciphertext made in the browser of the poster
+}
+
+#[derive(FromForm, Debug, Clone)]
+/// Form struct for the password of a password protected paste.
+pub struct PastePassword {
+    pub password: String,
+}
+
+impl NewPaste {
+    /// Returns the options the poster chose for this paste.
+    pub fn options(&self) -> PasteOptions {
+        PasteOptions {
+            expiry: self.expiry,
+            max_views: self.max_views,
+            burn_after_reading: self.burn_after_reading,
+            language: self.language.clone(),
+            visibility: self.visibility,
+            allowed_logins: self.allowed_logins.clone(),
+            password: self.password.clone(),
+            title: self.title.clone(),
+            description: self.description.clone(),
+            tags: self.tags.clone(),
+            list_publicly: self.list_publicly,
+        }
+    }
+}
+
+/// Reads the paste option fields of a multipart upload form, missing or malformed fields are left unset.
+fn multipart_options(multipart_form_data: &MultipartFormData) -> PasteOptions {
+    let field = |name: &str| {
+        multipart_form_data
+            .texts
+            .get(name)
+            .and_then(|fields| fields.first())
+            .map(|field| field.text.trim().to_string())
+    };
+    PasteOptions {
+        expiry: field("expiry").and_then(|name| PasteExpiry::from_name(&name)),
+        max_views: field("max_views").and_then(|max_views| max_views.parse().ok()),
+        burn_after_reading: field("burn_after_reading").is_some(),
+        language: field("language"),
+        visibility: field("visibility").and_then(|name| PasteVisibility::from_name(&name)),
+        allowed_logins: field("allowed_logins"),
+        password: field("password"),
+        title: field("title"),
+        description: field("description"),
+        tags: field("tags"),
+        list_publicly: field("list_publicly").is_some(),
+    }
+}
+
+/// Redirects the poster to the error page when their paste could not be created.
+fn error_redirect() -> Flash<Redirect> {
+    Flash::error(
+        Redirect::to(uri!("/error_message")),
+        "Unable to create paste.",
+    )
+}
+
+/// Redirects the poster to their new paste, unless it has limited views,
+/// in which case they are sent to a page with its link so they do not use up a view.
+/// The deletion token is only ever given to the poster, through a flash cookie read by the page they are sent to.
+fn redirect_to_new_paste(
+    paste_id: String,
+    is_view_limited: bool,
+    deletion_token: String,
+) -> Flash<Redirect> {
+    let redirect = match is_view_limited {
+        false => Redirect::to(uri!(view_paste(paste_id, _))),
+        true => Redirect::to(uri!(paste_created(paste_id))),
+    };
+    Flash::new(redirect, DELETION_TOKEN_FLASH, deletion_token)
 }
 
-#[post("/paste/upload/<filename>", data = "<paste>")]
+#[post("/paste/upload/<filename>?<options..>", data = "<paste>")]
 /// Route for uploading a file to the paste section using a post request program, not through browser.
 /// echo "this is a test" | curl --data-binary @- http://localhost:8080/paste/upload/<filename>
+/// The options of the paste can be chosen in the query, e.g. /paste/upload/<filename>?expiry=OneDay&max_views=5&language=rust&visibility=Unlisted
 pub async fn upload(
     paste: Data<'_>,
     state: &State<TYRState>,
     filename: String,
+    options: PasteOptions,
     req: SocketAddr,
     jar: &CookieJar<'_>,
     _require_verified: RequireVerifiedGuard,
-) -> Redirect {
-    let mut file_content = String::new();
+) -> Flash<Redirect> {
+    // read as bytes rather than a string, so binary files such as images are not lost.
+    let mut file_content = Vec::new();
     let _file_size = paste
         .open(1.megabytes())
-        .read_to_string(&mut file_content)
+        .read_to_end(&mut file_content)
         .await
         .unwrap_or_default();
-    let mut hasher = DefaultHasher::new();
-    file_content.hash(&mut hasher);
 
     let path = PathBuf::from(format!("./output/file_uploads/{}", filename));
     if !Path::new(&path).exists() {
         let mut file = match File::create(&path) {
             Ok(f) => f,
-            Err(_) => return Redirect::to(uri!("/error_message")),
+            Err(_) => return error_redirect(),
         };
 
-        match file.write_all(file_content.as_bytes()) {
+        match file.write_all(&file_content) {
             Ok(_) => {}
-            Err(_) => return Redirect::to(uri!("/error_message")),
+            Err(_) => return error_redirect(),
         }
 
         let _ = file.sync_all();
 
-        let mut lock = state.pastes.write().unwrap();
+        let mut paste = match options.apply(Paste::new_file_paste(path.clone(), &req, jar)) {
+            Ok(paste) => paste,
+            Err(err) => {
+                println!("{err}");
+                let _ = fs::remove_file(path);
+                return error_redirect();
+            }
+        };
+
+        let is_view_limited = paste.max_views.is_some();
+        let deletion_token = paste.create_deletion_token();
+        let paste_id = insert_new_paste(&mut state.pastes.write().unwrap(), paste);
 
-        lock.insert(
-            hasher.finish().to_string(),
-            Paste::new_file_paste(path, &req, jar),
+        queue_webhook(
+            state,
+            WebhookEvent::Upload {
+                paste_id: paste_id.clone(),
+                ip: req.ip().to_string(),
+                file_name: filename,
+            },
         );
 
-        Redirect::to(uri!("/"))
+        redirect_to_new_paste(paste_id, is_view_limited, deletion_token)
     } else {
-        Redirect::to(uri!("/error_message"))
+        error_redirect()
     }
 }
 
@@ -89,14 +207,26 @@ pub async fn upload_multipart(
     req: SocketAddr,
     jar: &CookieJar<'_>,
     _require_verified: RequireVerifiedGuard,
-) -> Redirect {
+) -> Flash<Redirect> {
     let options = MultipartFormDataOptions::with_multipart_form_data_fields(vec![
         MultipartFormDataField::text("data"), // this one allows for random txt files
         MultipartFormDataField::bytes("data"),
+        MultipartFormDataField::text("expiry"),
+        MultipartFormDataField::text("max_views"),
+        MultipartFormDataField::text("burn_after_reading"),
+        MultipartFormDataField::text("language"),
+        MultipartFormDataField::text("visibility"),
+        MultipartFormDataField::text("allowed_logins"),
+        MultipartFormDataField::text("password"),
+        MultipartFormDataField::text("title"),
+        MultipartFormDataField::text("description"),
+        MultipartFormDataField::text("tags"),
+        MultipartFormDataField::text("list_publicly"),
     ]);
 
     match MultipartFormData::parse(content_type, data, options).await {
         Ok(multipart_form_data) => {
+            let options = multipart_options(&multipart_form_data);
             if let Some(file) = multipart_form_data.texts.get("data") {
                 if let Some(text_field) = file.get(0) {
                     let timestamp = Local::now();
@@ -118,7 +248,7 @@ pub async fn upload_multipart(
                     match fs::create_dir_all(path_without_file) {
                         Ok(_) => {}
                         Err(_) => {
-                            return Redirect::to(uri!("/error_message"));
+                            return error_redirect();
                         }
                     } // create all directories needed
 
@@ -126,28 +256,45 @@ pub async fn upload_multipart(
                         let mut file = match File::create(path.clone()) {
                             Ok(f) => f,
                             Err(_) => {
-                                return Redirect::to(uri!("/error_message"));
+                                return error_redirect();
                             }
                         };
 
                         match file.write_all(text_field.text.as_bytes()) {
                             Ok(_) => {}
-                            Err(_) => return Redirect::to(uri!("/error_message")),
+                            Err(_) => return error_redirect(),
                         }
 
                         let _ = file.sync_all();
 
-                        let mut hasher = DefaultHasher::new();
-                        text_field.text.hash(&mut hasher);
+                        let mut paste = match options.apply(Paste::new_file_paste_with_date(
+                            path.clone(),
+                            &req,
+                            jar,
+                            timestamp,
+                        )) {
+                            Ok(paste) => paste,
+                            Err(err) => {
+                                println!("{err}");
+                                let _ = fs::remove_file(path);
+                                return error_redirect();
+                            }
+                        };
+                        let is_view_limited = paste.max_views.is_some();
+                        let deletion_token = paste.create_deletion_token();
 
-                        let mut lock = state.pastes.write().unwrap();
+                        let paste_id = insert_new_paste(&mut state.pastes.write().unwrap(), paste);
 
-                        lock.insert(
-                            hasher.finish().to_string(),
-                            Paste::new_file_paste_with_date(path, &req, jar, timestamp),
+                        queue_webhook(
+                            state,
+                            WebhookEvent::Upload {
+                                paste_id: paste_id.clone(),
+                                ip: req.ip().to_string(),
+                                file_name: text_field.file_name.clone().unwrap_or_default(),
+                            },
                         );
 
-                        return Redirect::to(uri!("/"));
+                        return redirect_to_new_paste(paste_id, is_view_limited, deletion_token);
                     }
                 }
             } else if let Some(raw_bytes_vec) = multipart_form_data.raw.get("data") {
@@ -174,7 +321,7 @@ pub async fn upload_multipart(
                     match fs::create_dir_all(path_without_file) {
                         Ok(_) => {}
                         Err(_) => {
-                            return Redirect::to(uri!("/error_message"));
+                            return error_redirect();
                         }
                     }
 
@@ -182,7 +329,7 @@ pub async fn upload_multipart(
                         let mut file = match File::create(path.clone()) {
                             Ok(f) => f,
                             Err(_) => {
-                                return Redirect::to(uri!("/error_message"));
+                                return error_redirect();
                             }
                         };
 
@@ -190,30 +337,82 @@ pub async fn upload_multipart(
                             Ok(_) => {
                                 // println!("ok file write all");
                             }
-                            Err(_) => return Redirect::to(uri!("/error_message")),
+                            Err(_) => return error_redirect(),
                         }
 
                         let _ = file.sync_all();
 
-                        let mut hasher = DefaultHasher::new();
-                        vec_bytes.hash(&mut hasher);
+                        let mut paste = match options.apply(Paste::new_file_paste_with_date(
+                            path.clone(),
+                            &req,
+                            jar,
+                            timestamp,
+                        )) {
+                            Ok(paste) => paste,
+                            Err(err) => {
+                                println!("{err}");
+                                let _ = fs::remove_file(path);
+                                return error_redirect();
+                            }
+                        };
+                        let is_view_limited = paste.max_views.is_some();
+                        let deletion_token = paste.create_deletion_token();
 
-                        let mut lock = state.pastes.write().unwrap();
-                        let file_hash = hasher.finish().to_string();
+                        let paste_id = insert_new_paste(&mut state.pastes.write().unwrap(), paste);
 
-                        lock.insert(
-                            file_hash.clone(),
-                            Paste::new_file_paste_with_date(path, &req, jar, timestamp),
+                        queue_webhook(
+                            state,
+                            WebhookEvent::Upload {
+                                paste_id: paste_id.clone(),
+                                ip: req.ip().to_string(),
+                                file_name: raw_bytes_data.file_name.clone().unwrap_or_default(),
+                            },
                         );
 
-                        return Redirect::to(uri!(view_paste(file_hash)));
+                        return redirect_to_new_paste(paste_id, is_view_limited, deletion_token);
                     }
                 }
             }
         }
         Err(_err) => {}
     }
-    return Redirect::to(uri!("/error_message"));
+    error_redirect()
+}
+
+/// Returns the custom url chosen by the poster if it can be used.
+/// Custom urls are only for verified users, and need to be non empty, ascii, and not contain a space or a slash.
+pub fn valid_custom_url(custom_url: Option<&str>, is_verified: bool) -> Option<String> {
+    custom_url
+        .filter(|_| is_verified)
+        .filter(|possible_url| {
+            !possible_url.is_empty()
+                && !possible_url.contains(' ')
+                && possible_url.is_ascii()
+                && !possible_url.contains('/')
+                && !possible_url.contains('\\')
+        })
+        .map(|possible_url| html_escape::encode_safe(possible_url).to_string())
+}
+
+/// Inserts the paste at its custom url, or at a generated id if it has none, returning the id it was inserted at.
+pub fn insert_paste_at(
+    pastes: &mut HashMap<String, Paste>,
+    paste: Paste,
+    custom_url: Option<String>,
+) -> String {
+    match custom_url {
+        Some(custom_url) if !pastes.contains_key(&custom_url) => {
+            pastes.insert(custom_url.clone(), paste);
+            custom_url
+        }
+        custom_url => {
+            // a custom url that is already taken is never overwritten, the paste gets a generated id instead.
+            if let Some(custom_url) = custom_url {
+                println!("Custom url {custom_url} is already taken, generating an id instead.");
+            }
+            insert_new_paste(pastes, paste)
+        }
+    }
 }
 
 #[post("/paste/new", data = "<paste>")]
@@ -225,186 +424,890 @@ pub fn new_paste_post(
     state: &State<TYRState>,
     jar: &CookieJar,
     is_verified: GetVerifiedGuard,
-) -> Redirect {
-    let mut hasher = DefaultHasher::new();
-    paste.text.hash(&mut hasher);
-    let text_hash = hasher.finish();
-    let mut lock = state.pastes.write().unwrap();
-    let paste_struct = Paste::new(paste.text.clone(), &req, jar);
-
-    // custom url is either the forms given custom url, or the text hash if no custom url is given, or is invalid.
-    let custom_url = {
-        let possible_url = paste.custom_url.clone().unwrap_or(text_hash.to_string());
-        // we need to guarantee that the url given to the paste needs to contain either a hash, or a custom set url, and that it cant be empty, contain a space, or not be ascii
-        if possible_url.is_empty()
-            || possible_url.contains(' ')
-            || !possible_url.is_ascii()
-            || possible_url.contains('/')
-            || possible_url.contains('\\')
-        {
-            text_hash.to_string()
-        } else {
-            html_escape::encode_safe(&possible_url).to_string()
+) -> Flash<Redirect> {
+    // a fork inherits the language of the original paste, unless the poster chose one.
+    let fork = paste
+        .forked_from
+        .as_deref()
+        .and_then(|forked_from| fork_source(state, jar, forked_from));
+    let mut options = paste.options();
+    if options.language.as_deref().unwrap_or_default().is_empty() {
+        options.language = fork.as_ref().and_then(|(_, language)| language.clone());
+    }
+    let mut paste_struct = match options.apply(Paste::new(paste.text.clone(), &req, jar)) {
+        Ok(paste_struct) => paste_struct,
+        Err(err) => {
+            println!("{err}");
+            return error_redirect();
         }
     };
+    if fork.is_some() {
+        paste_struct.forked_from = paste.forked_from.clone();
+    }
+    if paste.short_link {
+        match short_link_url(&paste.text).filter(|_| is_verified.0) {
+            Some(url) => paste_struct.content = PasteContents::Redirect(url),
+            None => {
+                println!("Refused short link to {:?}", paste.text);
+                return error_redirect();
+            }
+        }
+    }
+    if paste.client_encrypted {
+        if !is_valid_ciphertext(&paste.text) {
+            println!("Refused encrypted paste that is not ciphertext.");
+            return error_redirect();
+        }
+        paste_struct.client_encrypted = true;
+    }
+    let mut lock = state.pastes.write().unwrap();
+    // the poster of a short link is sent to its created page, as viewing it would redirect them away and count a click.
+    let is_view_limited = paste_struct.max_views.is_some() || paste.short_link;
+    let deletion_token = paste_struct.create_deletion_token();
 
-    let url_already_exists = { lock.iter().map(|(id, _)| id).any(|id| id == &custom_url) }; // variable for if the given custom url already exists
-
-    if is_verified.0 && !url_already_exists {
-        // if the user is both verified, and this given custom url does not exist.
+    let custom_url = valid_custom_url(paste.custom_url.as_deref(), is_verified.0);
 
-        lock.insert(custom_url.clone(), paste_struct);
-        let uri = uri!(view_paste(custom_url));
-        Redirect::to(uri)
-    } else {
-        // require users paste to meet requirements of length
-        if paste.text.len() <= PASTE_LENGTH_CAP && paste.text.len() >= PASTE_LENGTH_MIN {
-            lock.insert(text_hash.to_string(), paste_struct);
-            let uri = uri!(view_paste(text_hash.to_string()));
-            Redirect::to(uri)
-        } else {
-            Redirect::to(uri!("/error_message"))
-        }
+    // require unverified users paste to meet requirements of length, encrypted pastes are measured by the length of the text before it was encrypted.
+    let text_length = match paste.client_encrypted {
+        true => plaintext_length(&paste.text).unwrap_or_default(),
+        false => paste.text.len(),
+    };
+    if !is_verified.0 && (text_length > PASTE_LENGTH_CAP || text_length < PASTE_LENGTH_MIN) {
+        return error_redirect();
     }
+
+    let paste_id = insert_paste_at(&mut lock, paste_struct, custom_url);
+    drop(lock);
+
+    queue_webhook(
+        state,
+        WebhookEvent::NewPaste {
+            paste_id: paste_id.clone(),
+            ip: req.ip().to_string(),
+        },
+    );
+    redirect_to_new_paste(paste_id, is_view_limited, deletion_token)
 }
 
 #[get("/paste/view/<paste_id>/file")]
 /// Page for viewing created pastes, attempts to have the user download the paste.
 pub async fn download_file_paste(
     paste_id: String,
-    _req: SocketAddr,
+    req: SocketAddr,
     state: &State<TYRState>,
+    jar: &CookieJar<'_>,
 ) -> Result<DownloadResponse, Status> {
-    let mut binding = state.pastes.write().unwrap().clone();
-    let paste_opt = binding.get_mut(&paste_id.clone());
-    match paste_opt {
-        None => Err(Default::default()),
-        Some(paste) => {
-            // FIXME: this does not seem to want to work every time, maybe change to an atomic but I dont see why this does not work.
-            //  Potentially because this is an async function?
-            paste.download_count += 1;
-            paste.time_of_last_download = Local::now();
-
-            match &paste.content {
-                PasteContents::File(path) => {
-                    let file_name = path.file_name().unwrap().to_str();
-                    DownloadResponse::from_file(path.clone().into_boxed_path(), file_name, None)
-                        .await
-                        .map_err(|err| {
-                            if err.kind() == ErrorKind::NotFound {
-                                Status::NotFound
-                            } else {
-                                Status::InternalServerError
-                            }
-                        })
-                }
-                PasteContents::PlainText(text) => Ok(DownloadResponse::from_vec(
-                    text.clone().into_bytes(),
-                    Some(paste_id),
-                    None,
-                )),
+    if remove_if_expired(state, &paste_id) {
+        return Err(Status::NotFound);
+    }
+    if !viewer_can_view(state, jar, &paste_id) {
+        return Err(Status::Forbidden);
+    }
+    if !viewer_has_password_access(state, jar, &paste_id) {
+        return Err(Status::Unauthorized);
+    }
+
+    let viewer_login = jar.get("login").map(|cookie| cookie.value().to_string());
+    let visitor = counted_visitor(state, viewer_login.as_deref(), req.ip());
+    // only a read lock is needed to count the download, the counters of the paste are atomic.
+    let (content, last_allowed_download) = {
+        let lock = state.pastes.read().unwrap();
+        match lock.get(&paste_id) {
+            Some(paste) if matches!(paste.content, PasteContents::Redirect(_)) => (None, false),
+            // the views were used up by someone else while this download was being checked.
+            Some(paste) if !paste.record_download(visitor.as_deref()) => (None, false),
+            Some(paste) => (
+                Some(paste.content.clone()),
+                paste.views_remaining() == Some(0),
+            ),
+            None => (None, false),
+        }
+    };
+
+    let response = match content {
+        None | Some(PasteContents::Redirect(_)) => Err(Status::NotFound),
+        Some(PasteContents::File(path)) => {
+            let file_name = path.file_name().unwrap().to_str();
+            DownloadResponse::from_file(path.clone().into_boxed_path(), file_name, None)
+                .await
+                .map_err(|err| {
+                    if err.kind() == ErrorKind::NotFound {
+                        Status::NotFound
+                    } else {
+                        Status::InternalServerError
+                    }
+                })
+        }
+        Some(PasteContents::PlainText(text)) => Ok(DownloadResponse::from_vec(
+            text.into_bytes(),
+            Some(paste_id.clone()),
+            None,
+        )),
+    };
+
+    // the file is already open by this point, so the paste can be deleted without interrupting this download.
+    if last_allowed_download {
+        delete_paste(state, &paste_id);
+    }
+
+    response
+}
+
+/// Returns the id the viewer is counted under, or none if unique visitors are not counted.
+pub fn counted_visitor(state: &State<TYRState>, login: Option<&str>, ip: IpAddr) -> Option<String> {
+    state
+        .counter_config
+        .read()
+        .unwrap()
+        .unique_visitors
+        .then(|| visitor_id(login, &ip.to_string()))
+}
+
+/// Returns false if the paste exists, but the viewer is not allowed to view it.
+fn viewer_can_view(state: &State<TYRState>, jar: &CookieJar, paste_id: &str) -> bool {
+    let is_admin = check_is_admin(state, jar);
+    let viewer_login = jar.get("login").map(|cookie| cookie.value().to_string());
+    state
+        .pastes
+        .read()
+        .unwrap()
+        .get(paste_id)
+        .map(|paste| paste.can_view(viewer_login.as_deref(), is_admin))
+        .unwrap_or(true)
+}
+
+/// Returns false if the paste exists and is password protected, but the viewer has not entered its password.
+pub fn viewer_has_password_access(
+    state: &State<TYRState>,
+    jar: &CookieJar,
+    paste_id: &str,
+) -> bool {
+    let is_protected = state
+        .pastes
+        .read()
+        .unwrap()
+        .get(paste_id)
+        .map(|paste| paste.password_hash.is_some())
+        .unwrap_or(false);
+    !is_protected || check_is_admin(state, jar) || has_access(jar, paste_id)
+}
+
+/// Returns the text and language of a paste being forked, or none if the viewer can not read it.
+/// Pastes with limited views can not be forked, as forking would read them without using up a view.
+fn fork_source(
+    state: &State<TYRState>,
+    jar: &CookieJar,
+    paste_id: &str,
+) -> Option<(String, Option<String>)> {
+    if !viewer_can_view(state, jar, paste_id) || !viewer_has_password_access(state, jar, paste_id) {
+        return None;
+    }
+    state
+        .pastes
+        .read()
+        .unwrap()
+        .get(paste_id)
+        .filter(|paste| !paste.is_expired() && paste.max_views.is_none() && !paste.client_encrypted)
+        .and_then(|paste| Some((paste.current_text()?, paste.language.clone())))
+}
+
+/// Page asking for the password of a password protected paste.
+fn password_prompt(paste_id: &str, error: Option<&str>) -> RawHtml<String> {
+    let back_button = "<button onclick=\"window.location.href=\'/\';\">Go back</button>";
+    let unlock_url = format!("/paste/view/{}/unlock", paste_id);
+    RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            p {"This paste is password protected."}
+            @if let Some(error) = error {
+                p {(error)}
+            }
+            form action=(unlock_url) method="post" {
+                label for="password" {"Enter password"}
+                br;
+                input type="password" name="password" id="password";
+                input type="submit" value="View paste";
+            }
+        }
+        .into_string(),
+    )
+}
+
+#[post("/paste/view/<paste_id>/unlock", data = "<password>")]
+/// Route for entering the password of a password protected paste, gives a short lived access cookie for the paste if it is correct.
+/// Wrong passwords are counted against the ip, which is refused for a while once it has given too many.
+pub fn unlock_paste(
+    paste_id: String,
+    password: Form<PastePassword>,
+    req: SocketAddr,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> Either<Redirect, RawHtml<String>> {
+    if try_paste_password(state, req.ip(), &paste_id, &password.password) {
+        grant_access(jar, &paste_id);
+        Either::Left(Redirect::to(uri!(view_paste(paste_id, _))))
+    } else {
+        Either::Right(password_prompt(
+            &paste_id,
+            Some("Incorrect password, or too many wrong passwords, try again later."),
+        ))
+    }
+}
+
+/// Deletes the paste if it has expired, then checks the viewer may view it.
+/// Returns the page to show instead of the paste if the paste is private, or needs a password the viewer has not entered.
+pub fn paste_access_denied(
+    state: &State<TYRState>,
+    jar: &CookieJar,
+    paste_id: &str,
+) -> Option<RawHtml<String>> {
+    remove_if_expired(state, paste_id);
+
+    let back_button = "<button onclick=\"window.location.href=\'/\';\">Go back</button>";
+    if !viewer_can_view(state, jar, paste_id) {
+        return Some(RawHtml(
+            html! {
+                (PreEscaped(back_button))
+                p {"This paste is private, log in with a login allowed to view it."}
             }
+            .into_string(),
+        ));
+    }
+    if !viewer_has_password_access(state, jar, paste_id) {
+        return Some(password_prompt(paste_id, None));
+    }
+    None
+}
+
+/// Deletes the paste if it has expired, returning true if it was deleted.
+pub fn remove_if_expired(state: &TYRState, paste_id: &str) -> bool {
+    let is_expired = state
+        .pastes
+        .read()
+        .unwrap()
+        .get(paste_id)
+        .map(|paste| paste.is_expired())
+        .unwrap_or(false);
+    if is_expired {
+        delete_paste(state, paste_id);
+    }
+    is_expired
+}
+
+/// Describes when a paste will be deleted, or none if it lasts forever.
+fn lifetime_info(paste: &Paste) -> Option<String> {
+    let expiry = paste.expires_at.map(|expires_at| {
+        format!(
+            "This paste expires at {}.",
+            expires_at.format("%Y-%m-%d %H:%M:%S")
+        )
+    });
+    let views = paste.views_remaining().map(|remaining| match remaining {
+        0 => "This was the last view of this paste, it has now been deleted.".to_string(),
+        1 => "This paste will be deleted after 1 more view.".to_string(),
+        remaining => format!("This paste will be deleted after {} more views.", remaining),
+    });
+    match (expiry, views) {
+        (None, None) => None,
+        (expiry, views) => Some(
+            [expiry, views]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<String>>()
+                .join(" "),
+        ),
+    }
+}
+
+/// The raw bytes of a paste, sent with the content type detected from the paste.
+pub struct RawPaste {
+    pub content_type: ContentType,
+    pub bytes: Vec<u8>,
+}
+
+impl<'r> Responder<'r, 'static> for RawPaste {
+    fn respond_to(self, _request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        Response::build()
+            .header(self.content_type)
+            // browsers must not guess a more dangerous content type than the one given.
+            .raw_header("X-Content-Type-Options", "nosniff")
+            .sized_body(self.bytes.len(), Cursor::new(self.bytes))
+            .ok()
+    }
+}
+
+/// Detects the content type of a file paste from its extension, or from its contents if the extension is unknown.
+/// Content types a browser would run scripts from are sent as plain text, so an upload can not run scripts on this site.
+pub fn detect_content_type(file_name: Option<&str>, bytes: &[u8]) -> ContentType {
+    let by_extension = file_name
+        .and_then(|file_name| Path::new(file_name).extension())
+        .and_then(|extension| extension.to_str())
+        .and_then(ContentType::from_extension);
+
+    match by_extension {
+        Some(content_type)
+            if content_type.is_html()
+                || content_type.is_javascript()
+                || content_type.is_xml()
+                || content_type.is_svg() =>
+        {
+            ContentType::Plain
         }
+        Some(content_type) => content_type,
+        None if std::str::from_utf8(bytes).is_ok() => ContentType::Plain,
+        None => ContentType::Binary,
+    }
+}
+
+/// Reads the raw bytes of a paste for the raw endpoint, counting it as a view.
+/// Expired pastes, and pastes whose views have been used up by this read, are deleted.
+fn read_raw_paste(
+    state: &State<TYRState>,
+    jar: &CookieJar,
+    ip: IpAddr,
+    paste_id: &str,
+    password: Option<&str>,
+) -> Result<RawPaste, Status> {
+    if remove_if_expired(state, paste_id) {
+        return Err(Status::NotFound);
+    }
+    if !viewer_can_view(state, jar, paste_id) {
+        return Err(Status::Forbidden);
+    }
+    let has_access = viewer_has_password_access(state, jar, paste_id)
+        || password
+            .map(|password| try_paste_password(state, ip, paste_id, password))
+            .unwrap_or(false);
+    if !has_access {
+        return Err(Status::Unauthorized);
     }
+    let viewer_login = jar.get("login").map(|cookie| cookie.value().to_string());
+    let visitor = counted_visitor(state, viewer_login.as_deref(), ip);
+    take_raw_paste(state, paste_id, visitor.as_deref())
 }
 
-#[get("/paste/view/<paste_id>")]
+/// Reads the raw bytes of a paste the reader has already been given access to, counting it as a view.
+/// The paste is deleted if this read used up its last view.
+pub fn take_raw_paste(
+    state: &State<TYRState>,
+    paste_id: &str,
+    visitor: Option<&str>,
+) -> Result<RawPaste, Status> {
+    let (content, views_used_up) = {
+        let lock = state.pastes.read().unwrap();
+        let paste = lock.get(paste_id).ok_or(Status::NotFound)?;
+        if !paste.record_view(visitor) {
+            return Err(Status::NotFound);
+        }
+        (paste.content.clone(), paste.views_remaining() == Some(0))
+    };
+
+    let raw_paste = match content {
+        PasteContents::File(path) => {
+            let bytes = fs::read(&path).map_err(|err| {
+                if err.kind() == ErrorKind::NotFound {
+                    Status::NotFound
+                } else {
+                    Status::InternalServerError
+                }
+            });
+            bytes.map(|bytes| RawPaste {
+                content_type: detect_content_type(
+                    path.file_name().and_then(|name| name.to_str()),
+                    &bytes,
+                ),
+                bytes,
+            })
+        }
+        // the raw contents of a short link are its url, so clients can read where it leads without following it.
+        PasteContents::PlainText(text) | PasteContents::Redirect(text) => Ok(RawPaste {
+            content_type: ContentType::Plain,
+            bytes: text.into_bytes(),
+        }),
+    };
+
+    if views_used_up {
+        delete_paste(state, paste_id);
+    }
+
+    raw_paste
+}
+
+#[get("/paste/raw/<paste_id>?<password>")]
+/// Route for reading the contents of a paste without any html, meant for command line clients.
+/// curl http://localhost:8080/paste/raw/<paste_id>
+/// Password protected pastes take their password in the query, e.g. /paste/raw/<paste_id>?password=hunter2
+/// The password query is only meant for the api and command line clients, browsers unlock the paste on its page instead
+/// so the password never ends up in their history. Wrong passwords count towards the same limit as the unlock page.
+pub fn raw_paste(
+    paste_id: String,
+    password: Option<String>,
+    req: SocketAddr,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> Result<RawPaste, Status> {
+    read_raw_paste(state, jar, req.ip(), &paste_id, password.as_deref())
+}
+
+/// Follows a short link, counting it as a view, or returns none if the paste is not a short link the viewer may follow.
+/// Short links the viewer can not follow, such as ones behind a password they have not entered, are left to the paste page to explain.
+fn follow_short_link(
+    state: &State<TYRState>,
+    jar: &CookieJar,
+    ip: IpAddr,
+    paste_id: &str,
+) -> Option<Redirect> {
+    if remove_if_expired(state, paste_id)
+        || !viewer_can_view(state, jar, paste_id)
+        || !viewer_has_password_access(state, jar, paste_id)
+    {
+        return None;
+    }
+    let viewer_login = jar.get("login").map(|cookie| cookie.value().to_string());
+    let visitor = counted_visitor(state, viewer_login.as_deref(), ip);
+
+    let (url, views_used_up) = {
+        let lock = state.pastes.read().unwrap();
+        let paste = lock.get(paste_id)?;
+        let url = match &paste.content {
+            PasteContents::Redirect(url) => url.clone(),
+            _ => return None,
+        };
+        if !paste.record_view(visitor.as_deref()) {
+            return None;
+        }
+        (url, paste.views_remaining() == Some(0))
+    };
+    if views_used_up {
+        delete_paste(state, paste_id);
+    }
+    Some(Redirect::to(url))
+}
+
+#[get("/s/<paste_id>")]
+/// Short url of a paste, short links are followed straight away, and any other paste is shown on its page.
+pub fn short_link(
+    paste_id: String,
+    req: SocketAddr,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> Redirect {
+    follow_short_link(state, jar, req.ip(), &paste_id)
+        .unwrap_or_else(|| Redirect::to(uri!(view_paste(paste_id, _))))
+}
+
+#[get("/paste/view/<paste_id>?<raw>")]
 /// Page for viewing created pastes, viewing only, download optional.
+/// Markdown, csv and images are rendered, with ?raw=true showing the plain contents instead.
+/// Command line clients, and clients that prefer plain text, are given the raw paste instead.
 pub fn view_paste(
     paste_id: String,
-    _req: SocketAddr,
+    raw: Option<bool>,
+    req: SocketAddr,
     state: &State<TYRState>,
     jar: &CookieJar,
-) -> RawHtml<String> {
-    let mut binding = state.pastes.write().unwrap();
-    let paste_opt = binding.get_mut(&paste_id);
+    wants_plain_text: WantsPlainTextGuard,
+    flash: Option<FlashMessage<'_>>,
+) -> Either<RawHtml<String>, Either<Redirect, Result<RawPaste, Status>>> {
+    if let Some(redirect) = follow_short_link(state, jar, req.ip(), &paste_id) {
+        return Either::Right(Either::Left(redirect));
+    }
+    if wants_plain_text.0 {
+        return Either::Right(Either::Right(read_raw_paste(
+            state,
+            jar,
+            req.ip(),
+            &paste_id,
+            None,
+        )));
+    }
+
+    if let Some(denied_page) = paste_access_denied(state, jar, &paste_id) {
+        return Either::Left(denied_page);
+    }
+
     let back_button = "<button onclick=\"window.location.href=\'/\';\">Go back</button>";
-    let file_button = format!(
-        "<button onclick=\"window.location.href=\'/paste/view/{}/file\';\">Download file</button>",
-        paste_id
-    );
+
+    let viewer_login = jar.get("login").map(|cookie| cookie.value().to_string());
+    // the view is counted through the atomic counters of the paste, so only a read lock is held, and only until the contents are copied out.
+    let binding = state.pastes.read().unwrap();
+    let forks = forks_of(&binding, &paste_id);
+    let forked_from = binding
+        .get(&paste_id)
+        .and_then(|paste| paste.forked_from.clone());
+    let (title, description, tags) = binding
+        .get(&paste_id)
+        .map(|paste| {
+            (
+                paste.title.clone(),
+                paste.description.clone(),
+                paste.tags.clone(),
+            )
+        })
+        .unwrap_or_default();
+    let can_fork = binding
+        .get(&paste_id)
+        .map(|paste| paste.max_views.is_none() && !paste.client_encrypted)
+        .unwrap_or(false);
+    let paste_opt = binding.get(&paste_id);
+    let is_poster = match (&paste_opt, &viewer_login) {
+        (Some(paste), Some(viewer_login)) => paste.poster_login() == Some(viewer_login.as_str()),
+        _ => false,
+    };
+    let delete_url = format!("/paste/delete/{}", paste_id);
+    let edit_link = format!("/paste/edit/{}", paste_id);
+    let history_link = format!("/paste/view/{}/history", paste_id);
+    let fork_link = format!("/paste/new?fork={}", paste_id);
+    // the history of a paste with limited views is not viewable, see paste_revisions.
+    let has_revisions = paste_opt
+        .as_ref()
+        .map(|paste| !paste.revisions.is_empty() && paste.max_views.is_none())
+        .unwrap_or(false);
 
     let is_admin = check_is_admin(state, jar);
     let paste_info = match paste_opt {
         None => "Paste does not exist, no metrics available.".to_string(),
-        Some(ref paste) => {
-            format!("View Count: {},Download count: {}, Ip of poster: {}, Time of last view: {}, Time of last download: {}, Login cookie: {:?}, Post time: {}",
-                    paste.view_count, paste.download_count,paste.ip_of_poster,
+        Some(paste) => {
+            format!("View Count: {},Download count: {}, Unique visitors: {}, Ip of poster: {}, Time of last view: {}, Time of last download: {}, Login cookie: {:?}, Post time: {}, Expires at: {:?}, Max views: {:?}, Visibility: {:?}, Allowed logins: {:?}",
+                    paste.view_count, paste.download_count, paste.visitors.len(), paste.ip_of_poster,
                     paste.time_of_last_view,paste.time_of_last_download,paste.login_cookie_of_poster,
-            paste.post_time)
+            paste.post_time, paste.expires_at, paste.max_views, paste.visibility, paste.allowed_logins)
         }
     };
 
-    let escaped = match paste_opt {
-        None => paste_404(),
+    let visitor = counted_visitor(state, viewer_login.as_deref(), req.ip());
+    let paste_exists = paste_opt.is_some();
+    let mut lifetime = None;
+    let mut views_used_up = false;
+    let mut is_encrypted = false;
+    let contents = match paste_opt {
+        // the views were used up by someone else while this view was being checked.
+        Some(paste) if !paste.record_view(visitor.as_deref()) => None,
         Some(paste) => {
-            paste.view_count += 1;
-            paste.time_of_last_view = Local::now();
-            match &paste.content {
-                PasteContents::File(path) => {
-                    match File::open(path).ok() {
-                        None => "File un-readable. Error occurred.".to_string(),
-                        Some(mut file) => {
-                            let mut file_contents = String::new();
-                            file.read_to_string(&mut file_contents).unwrap_or_default();
-                            let escaped = html_escape::encode_safe(&file_contents); // escape so no xss can happen!
-                            escaped.to_string()
-                        }
+            lifetime = lifetime_info(paste);
+            views_used_up = paste.views_remaining() == Some(0);
+            is_encrypted = paste.client_encrypted;
+            Some((paste.content.clone(), paste.language.clone()))
+        }
+        None => None,
+    };
+    drop(binding);
+
+    let raw = raw.unwrap_or(false);
+    let download_link = format!("/paste/view/{}/file", paste_id);
+    let contents_html = match contents {
+        None => html! { p {(paste_404())} }.into_string(),
+        Some((PasteContents::File(path), language)) => match fs::read(&path).ok() {
+            None => html! { p {"File un-readable. Error occurred."} }.into_string(),
+            Some(bytes) => {
+                let file_name = path.file_name().and_then(|name| name.to_str());
+                let mode = RenderMode::detect(language.as_deref(), file_name, &bytes);
+                // every mode escapes or sanitizes the contents, so no xss can happen!
+                render_paste(
+                    &bytes,
+                    mode,
+                    raw,
+                    language.as_deref(),
+                    file_name,
+                    &download_link,
+                )
+            }
+        },
+        Some((PasteContents::Redirect(url), _)) => html! {
+            p {"Short link to " a href=(url) {(url)}}
+        }
+        .into_string(),
+        // the server only has the ciphertext, so the page decrypts it in the browser with the key from the link.
+        Some((PasteContents::PlainText(text), _)) if is_encrypted && !raw => {
+            render_decryption_page(&text)
+        }
+        Some((PasteContents::PlainText(text), language)) => {
+            let mode = RenderMode::detect(language.as_deref(), None, text.as_bytes());
+            render_paste(
+                text.as_bytes(),
+                mode,
+                raw,
+                language.as_deref(),
+                None,
+                &download_link,
+            )
+        }
+    };
+    // the paste id comes from the url, so links built from it are only ever rendered through maud, which escapes them.
+    let (raw_toggle_link, raw_toggle_label) = match raw {
+        true => (format!("/paste/view/{}", paste_id), "View rendered"),
+        false => (format!("/paste/view/{}?raw=true", paste_id), "View raw"),
+    };
+
+    // the paste has been read for the last time, so it is deleted now that its contents are rendered.
+    if views_used_up {
+        delete_paste(state, &paste_id);
+    }
+
+    Either::Left(RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            @if paste_exists && !views_used_up {
+                " "
+                a href=(download_link) {"Download file"}
+                " "
+                a href=(raw_toggle_link) {(raw_toggle_label)}
+            }
+            @if is_admin {
+                p {(paste_info)}
+            }
+            @if is_poster && !views_used_up {
+                form action=(delete_url) method="post" style="display:inline" {
+                    input type="submit" value="Delete paste";
+                }
+                @if !is_encrypted {
+                    " "
+                    a href=(edit_link) {"Edit paste"}
+                }
+            }
+            @if has_revisions {
+                " "
+                a href=(history_link) {"History"}
+            }
+            @if can_fork {
+                " "
+                a href=(fork_link) {"Fork paste"}
+            }
+            @if let Some(forked_from) = forked_from {
+                @let forked_from_link = format!("/paste/view/{}", forked_from);
+                p {"Forked from " a href=(forked_from_link) {(forked_from)}}
+            }
+            @if !forks.is_empty() {
+                p {
+                    "Forks: "
+                    @for fork in &forks {
+                        @let fork_link = format!("/paste/view/{}", fork);
+                        a href=(fork_link) {(fork)} " "
                     }
                 }
-                PasteContents::PlainText(text) => {
-                    let escaped = html_escape::encode_safe(&text); // escape so no xss can happen!
-                    escaped.replace("\r\n", "<br>").replace('\n', "<br>")
+            }
+            @if let Some(title) = title {
+                h2 {(title)}
+            }
+            @if let Some(description) = description {
+                p {(description)}
+            }
+            @if !tags.is_empty() {
+                p {
+                    "Tags: "
+                    @for tag in &tags {
+                        @let tag_link = format!("/paste/tag/{}", tag);
+                        a href=(tag_link) {"#" (tag)} " "
+                    }
                 }
             }
+            (deletion_token_info(&paste_id, flash))
+            @if let Some(lifetime) = lifetime {
+                p {(lifetime)}
+            }
+            (PreEscaped(contents_html))
         }
+        .into_string(),
+    ))
+}
+
+#[get("/paste/created/<paste_id>")]
+/// Page shown after creating a paste with limited views or a short link, gives the link to the paste without using up one of its views.
+pub fn paste_created(
+    paste_id: String,
+    state: &State<TYRState>,
+    flash: Option<FlashMessage<'_>>,
+) -> RawHtml<String> {
+    let back_button = "<button onclick=\"window.location.href=\'/\';\">Go back</button>";
+    let (lifetime, is_short_link, is_encrypted) = state
+        .pastes
+        .read()
+        .unwrap()
+        .get(&paste_id)
+        .map(|paste| {
+            (
+                lifetime_info(paste),
+                matches!(paste.content, PasteContents::Redirect(_)),
+                paste.client_encrypted,
+            )
+        })
+        .unwrap_or_default();
+    let link = match is_short_link {
+        true => format!("/s/{}", paste_id),
+        false => format!("/paste/view/{}", paste_id),
     };
 
     RawHtml(
         html! {
             (PreEscaped(back_button))
-            (PreEscaped(file_button))
-            @if is_admin {
-                p {(paste_info)}
+            p {"Your paste has been created, share this link with whoever should read it:"}
+            p { a id="paste-link" href=(link) {(link)} }
+            @if is_encrypted {
+                // the key of an encrypted paste is only in the fragment of this page, carried over from the form, so it is added to the link here.
+                (PreEscaped(r#"<script>
+                const pasteLink = document.getElementById("paste-link");
+                pasteLink.href += window.location.hash;
+                pasteLink.textContent = pasteLink.href;
+                </script>"#))
+                p {"The key after the # decrypts the paste, anyone without it only gets ciphertext."}
+            }
+            @if let Some(lifetime) = lifetime {
+                p {(lifetime)}
+            }
+            (deletion_token_info(&paste_id, flash))
+        }
+        .into_string(),
+    )
+}
+
+/// Shows the poster the deletion token of the paste they just created, if it was flashed to them.
+fn deletion_token_info(paste_id: &str, flash: Option<FlashMessage<'_>>) -> Markup {
+    let deletion_token = flash
+        .filter(|flash| flash.kind() == DELETION_TOKEN_FLASH)
+        .map(|flash| flash.message().to_string());
+    html! {
+        @if let Some(deletion_token) = deletion_token {
+            @let delete_link = format!("/paste/delete/{}?token={}", paste_id, deletion_token);
+            p {
+                "Keep this link secret, it is the only way to delete this paste without being logged in as its poster: "
+                a href=(delete_link) {(delete_link)}
+            }
+        }
+    }
+}
+
+/// Returns true if the viewer owns the paste, either with its deletion token, as its poster, or as an admin.
+/// Owners can delete and edit the paste.
+pub fn is_paste_owner(
+    state: &State<TYRState>,
+    jar: &CookieJar,
+    paste: &Paste,
+    token: Option<&str>,
+) -> bool {
+    let viewer_login = jar.get("login").map(|cookie| cookie.value().to_string());
+    let is_poster = viewer_login.is_some() && paste.poster_login() == viewer_login.as_deref();
+    let has_token = token
+        .map(|token| paste.check_deletion_token(token))
+        .unwrap_or(false);
+    is_poster || has_token || check_is_admin(state, jar)
+}
+
+#[get("/paste/delete/<paste_id>?<token>")]
+/// Page confirming the deletion of a paste, so following a deletion link does not delete the paste on its own.
+pub fn confirm_delete_paste(paste_id: String, token: Option<String>) -> RawHtml<String> {
+    let back_button = "<button onclick=\"window.location.href=\'/\';\">Go back</button>";
+    let delete_url = match &token {
+        None => format!("/paste/delete/{}", paste_id),
+        Some(token) => format!("/paste/delete/{}?token={}", paste_id, token),
+    };
+    RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            p {"Are you sure you want to delete this paste? This can not be undone."}
+            form action=(delete_url) method="post" {
+                input type="submit" value="Delete paste";
             }
-            p {(PreEscaped(escaped))}
         }
         .into_string(),
     )
 }
 
-#[get("/paste/new")]
+#[post("/paste/delete/<paste_id>?<token>")]
+/// Route for a poster deleting their own paste and its file, using either the deletion token given when creating it, or the login that posted it.
+pub fn delete_own_paste(
+    paste_id: String,
+    token: Option<String>,
+    state: &State<TYRState>,
+    jar: &CookieJar,
+) -> Result<RawHtml<String>, Status> {
+    let is_allowed = state
+        .pastes
+        .read()
+        .unwrap()
+        .get(&paste_id)
+        .map(|paste| is_paste_owner(state, jar, paste, token.as_deref()))
+        .ok_or(Status::NotFound)?;
+    if !is_allowed {
+        return Err(Status::Forbidden);
+    }
+
+    delete_paste(state, &paste_id);
+
+    let back_button = "<button onclick=\"window.location.href=\'/\';\">Go back</button>";
+    Ok(RawHtml(
+        html! {
+            (PreEscaped(back_button))
+            p {"Your paste has been deleted."}
+        }
+        .into_string(),
+    ))
+}
+
+#[get("/paste/new?<fork>")]
 /// Page for creating a new paste, different displayed page depending on if the user is verified.
+/// Forking a paste opens this page with the contents of the forked paste already filled in, e.g. /paste/new?fork=<paste_id>
 pub fn new_paste(
+    fork: Option<String>,
     _req: SocketAddr,
-    _state: &State<TYRState>,
+    state: &State<TYRState>,
+    jar: &CookieJar,
     is_verified: GetVerifiedGuard,
 ) -> RawHtml<String> {
     let back_button = "<button onclick=\"window.location.href=\'/\';\">Go back</button>";
+    let fork_text = fork
+        .as_deref()
+        .and_then(|fork| fork_source(state, jar, fork))
+        .map(|(text, _)| text);
+    let paste_text_input = html! {
+        @if let (Some(fork), Some(_)) = (&fork, &fork_text) {
+            @let fork_link = format!("/paste/view/{}", fork);
+            p {"Forking paste " a href=(fork_link) {(fork)} "."}
+            input type="hidden" name="forked_from" value=(fork);
+        } @else if fork.is_some() {
+            p {"The paste to fork does not exist, or you are not allowed to read it."}
+        }
+        label for="text" {"Enter paste"}
+        br;
+        textarea rows="5" cols="60" name="text" id="text" {(fork_text.unwrap_or_default())}
+        br;
+        (encrypt_in_browser_input())
+    };
+    let own_login_id = jar.get("login").map(|cookie| login_id(cookie.value()));
+    let login_id_info = html! {
+        @match own_login_id {
+            None => {
+                p {"Log in to create private pastes, or to be allowed to view other users private pastes."}
+            }
+            Some(own_login_id) => {
+                p {"Your login id is " b {(own_login_id)} ", share it with posters who want to let you view their private pastes."}
+            }
+        }
+    };
 
     if is_verified.0 {
         RawHtml(
             html! {
-            (PreEscaped(r#"
-            <form action="/paste/new" method="post">
-                <label for="ip">Enter paste</label>
-                <br>
-                    <textarea rows = "5" cols = "60" name = "text"></textarea>
-                    <br>
-                    <p>Custom url: </p>
-                    <input type="text" name="custom_url" id="custom_url">
-                <br>
-                <br>
-                <input type="submit" value="Submit paste">
-            </form>
-            <form id="form" enctype="multipart/form-data" method="post" action="/paste/upload">
-                <div class="input-group">
-                    <label for="files">Select files</label>
-                    <input id="file" name="data" type="file" multiple />
-                </div>
-            <button class="submit-btn" type="submit">Upload</button>
-        </form>
-    "#))
+                form action="/paste/new" method="post" {
+                    (paste_text_input)
+                    p {"Custom url: "}
+                    input type="text" name="custom_url" id="custom_url";
+                    br;
+                    input type="checkbox" name="short_link" id="short_link";
+                    label for="short_link" {"Short link, the paste is a url that visitors are redirected to"}
+                    (paste_options_inputs(""))
+                    br;
+                    br;
+                    input type="submit" value="Submit paste";
+                }
+                form id="form" enctype="multipart/form-data" method="post" action="/paste/upload" {
+                    div."input-group" {
+                        label for="file" {"Select files"}
+                        input id="file" name="data" type="file" multiple;
+                        (paste_options_inputs("upload_"))
+                    }
+                    button."submit-btn" type="submit" {"Upload"}
+                }
+                (login_id_info)
                 br;
                 (PreEscaped(back_button))
             }
@@ -414,15 +1317,13 @@ pub fn new_paste(
         // user is not verified
         RawHtml(
             html! {
-            (PreEscaped(r#"
-            <form action="/paste/new" method="post">
-                <label for="ip">Enter paste</label>
-                <br>
-                    <textarea rows = "5" cols = "60" name = "text"></textarea>
-                <br>
-                <input type="submit" value="Submit paste">
-            </form>
-    "#))
+                form action="/paste/new" method="post" {
+                    (paste_text_input)
+                    (paste_options_inputs(""))
+                    br;
+                    input type="submit" value="Submit paste";
+                }
+                (login_id_info)
                 br;
                 (PreEscaped(back_button))
             }
@@ -430,3 +1331,147 @@ pub fn new_paste(
         )
     }
 }
+
+/// The inputs for the options shared by every paste form, with the ids prefixed so that several forms can be on one page.
+fn paste_options_inputs(id_prefix: &str) -> Markup {
+    let id = |name: &str| format!("{}{}", id_prefix, name);
+    html! {
+        p {"Title (optional): "}
+        input type="text" name="title" id=(id("title")) maxlength="100";
+        p {"Description (optional): "}
+        textarea rows="2" cols="60" name="description" id=(id("description")) maxlength="500" {}
+        p {"Tags, separated by commas (optional): "}
+        input type="text" name="tags" id=(id("tags"));
+        br;
+        input type="checkbox" name="list_publicly" id=(id("list_publicly"));
+        label for=(id("list_publicly")) {"List publicly in the paste directory (public pastes only)"}
+        p {"Language (optional, detected if left empty): "}
+        input type="text" name="language" id=(id("language"));
+        p {"Expires after: "}
+        select name="expiry" id=(id("expiry")) {
+            option value="Never" {"Never"}
+            option value="TenMinutes" {"10 minutes"}
+            option value="OneDay" {"1 day"}
+            option value="OneWeek" {"1 week"}
+        }
+        p {"Maximum views (leave empty for unlimited): "}
+        input type="number" min="1" name="max_views" id=(id("max_views"));
+        br;
+        input type="checkbox" name="burn_after_reading" id=(id("burn_after_reading"));
+        label for=(id("burn_after_reading")) {"Burn after reading"}
+        p {"Visibility: "}
+        select name="visibility" id=(id("visibility")) {
+            option value="Public" {"Public"}
+            option value="Unlisted" {"Unlisted"}
+            option value="PrivateToLogin" {"Private to my login"}
+            option value="PrivateToLogins" {"Private to my login and listed logins"}
+        }
+        p {"Login ids allowed to view, separated by commas: "}
+        input type="text" name="allowed_logins" id=(id("allowed_logins"));
+        p {"Password (optional): "}
+        input type="password" name="password" id=(id("password"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pages::post_paste::{
+        confirm_delete_paste, delete_own_paste, detect_content_type, view_paste,
+    };
+    use crate::paste::{Paste, PasteContents};
+    use crate::state_management::TYRState;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::blocking::Client;
+    use std::fs;
+
+    #[test]
+    fn raw_content_types() {
+        assert_eq!(
+            detect_content_type(Some("cat.png"), &[0x89, 0x50]),
+            ContentType::PNG
+        );
+        assert_eq!(
+            detect_content_type(Some("page.html"), b"<script>"),
+            ContentType::Plain
+        );
+        assert_eq!(
+            detect_content_type(Some("image.svg"), b"<svg>"),
+            ContentType::Plain
+        );
+        assert_eq!(
+            detect_content_type(Some("notes"), b"lmao"),
+            ContentType::Plain
+        );
+        assert_eq!(
+            detect_content_type(None, &[0xff, 0xfe, 0x00]),
+            ContentType::Binary
+        );
+    }
+
+    #[test]
+    fn view_page_escapes_paste_ids() {
+        let rocket = rocket::build()
+            .manage(TYRState::default())
+            .mount("/", routes![view_paste]);
+        let client = Client::tracked(rocket).unwrap();
+        let page = client
+            .get("/paste/view/%27%3Balert(1)%3B%27%3Cb%3E")
+            .remote("127.0.0.1:8000".parse().unwrap())
+            .header(Header::new("User-Agent", "Mozilla/5.0"))
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(!page.contains("alert(1)"));
+        assert!(!page.contains("<b>"));
+    }
+
+    #[test]
+    fn deleting_with_a_deletion_token() {
+        let state = TYRState::default();
+        let file_path = std::env::temp_dir().join(format!(
+            "tyr_delete_test_{}.txt",
+            uuid::Uuid::new_v4().as_simple()
+        ));
+        fs::write(&file_path, "uploaded file").unwrap();
+        let mut paste = Paste {
+            content: PasteContents::File(file_path.clone()),
+            ..Paste::test_paste("")
+        };
+        let token = paste.create_deletion_token();
+        state
+            .pastes
+            .write()
+            .unwrap()
+            .insert("abc".to_string(), paste);
+
+        let rocket = rocket::build()
+            .manage(state.clone())
+            .mount("/", routes![confirm_delete_paste, delete_own_paste]);
+        let client = Client::tracked(rocket).unwrap();
+
+        let confirm_page = client
+            .get(format!("/paste/delete/abc?token={}", token))
+            .dispatch();
+        assert_eq!(confirm_page.status(), Status::Ok);
+        assert!(state.pastes.read().unwrap().contains_key("abc"));
+
+        let wrong_token = client.post("/paste/delete/abc?token=wrong").dispatch();
+        assert_eq!(wrong_token.status(), Status::Forbidden);
+        let no_token = client.post("/paste/delete/abc").dispatch();
+        assert_eq!(no_token.status(), Status::Forbidden);
+        assert!(state.pastes.read().unwrap().contains_key("abc"));
+        assert!(file_path.exists());
+
+        let deleted = client
+            .post(format!("/paste/delete/abc?token={}", token))
+            .dispatch();
+        assert_eq!(deleted.status(), Status::Ok);
+        assert!(!state.pastes.read().unwrap().contains_key("abc"));
+        assert!(!file_path.exists());
+
+        let missing = client
+            .post(format!("/paste/delete/abc?token={}", token))
+            .dispatch();
+        assert_eq!(missing.status(), Status::NotFound);
+    }
+}
@@ -1,10 +1,11 @@
-use crate::message::{Message, NewMessage};
+use crate::email::notify_new_message;
+use crate::message::{GuestbookEntry, Message, NewMessage};
 use crate::state_management::save_program_state;
 use crate::user::User;
 use crate::verified_guard::GetVerifiedGuard;
+use crate::webhook::{queue_webhook, WebhookEvent};
 use crate::TYRState;
-use crate::{MESSAGE_LENGTH_CAP, MESSAGE_LENGTH_MIN};
-use chrono::Utc;
+use crate::{GUESTBOOK_NAME_LENGTH_CAP, MESSAGE_LENGTH_CAP, MESSAGE_LENGTH_MIN};
 use rocket::form::Form;
 use rocket::http::CookieJar;
 use rocket::response::Redirect;
@@ -37,6 +38,18 @@ pub fn submit_message(
         }
     }
 
+    let guestbook = GuestbookEntry::from_new_message(&message);
+
+    if let Some(GuestbookEntry {
+        display_name: Some(name),
+        ..
+    }) = &guestbook
+    {
+        if name.len() > GUESTBOOK_NAME_LENGTH_CAP {
+            return Redirect::to(uri!("/too_long")); // display names are shown publicly, so they are always limited
+        }
+    }
+
     {
         let lock = state.messages.read().unwrap();
         match lock.get(user_ip) {
@@ -55,23 +68,54 @@ pub fn submit_message(
         }
     } // block for locking in read mode, the message list to check if the user is able to post, or if their message is a duplicate.
 
+    let encryption = state.admin_state.read().unwrap().message_encryption.clone();
+    let is_encrypted = encryption.is_some() && guestbook.is_none();
+
     {
         let mut lock = state.messages.write().unwrap();
+        let user_hash = match lock.get(user_ip) {
+            None => None,
+            Some(_) => jar.get("login").map(|cookie| cookie.value().to_string()),
+        };
+        // message object used for pushing to the user
+        let msg = match Message::new(
+            message.msg.to_string(),
+            user_hash,
+            guestbook,
+            encryption.as_ref(),
+        ) {
+            Ok(msg) => msg,
+            Err(err) => {
+                println!("{err}");
+                return Redirect::to(uri!("/error_message"));
+            }
+        };
         match lock.get_mut(user_ip) {
             None => {
-                let msg = Message {
-                    text: message.msg.to_string(),
-                    time_stamp: Utc::now(),
-                    user_hash: None,
-                }; // message object used for pushing to the user
                 lock.insert(user_ip.to_string(), User::new(msg)); // insert the new vector with the key of the users ip address
             }
             Some(user) => {
-                user.push(message.msg.to_string(), jar.get("login")); // push their new message, this also updates their last time of posting
+                user.push(msg); // push their new message, this also updates their last time of posting
             }
         };
     } // block for locking the message block in write mode.
 
+    // encrypted messages are only readable by the host from the admin page, so their text is never sent elsewhere.
+    let notification_text = match is_encrypted {
+        true => None,
+        false => Some(message.msg.as_str()),
+    };
+
+    queue_webhook(
+        state,
+        WebhookEvent::NewMessage {
+            ip: user_ip.to_string(),
+            text: notification_text.map(|text| text.to_string()),
+            logged_in: jar.get("login").is_some(),
+        },
+    );
+    notify_new_message(state, user_ip, notification_text);
+
     save_program_state(state, &PathBuf::from("./output/state.ser"));
 
     Redirect::to(uri!("/"))
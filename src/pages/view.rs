@@ -6,6 +6,9 @@ use rocket::response::content::RawHtml;
 use rocket::State;
 use std::net::SocketAddr;
 
+/// Text shown in place of a message that is encrypted to the host, and can not be read by the sender.
+static ENCRYPTED_MESSAGE_TEXT: &str = "[Encrypted, only readable by the host]";
+
 #[get("/view")]
 /// A page to view all messages sent by this specific user, uses their ip address to look them ip in the hash map.
 pub fn view(req: SocketAddr, state: &State<TYRState>, jar: &CookieJar) -> RawHtml<String> {
@@ -25,7 +28,10 @@ pub fn view(req: SocketAddr, state: &State<TYRState>, jar: &CookieJar) -> RawHtm
                     for msg in messages {
                         if let Some(hash) = &msg.user_hash {
                             if &cookie.value().to_string() == hash {
-                                let escaped = html_escape::encode_safe(&msg.text);
+                                let escaped = match msg.ciphertext {
+                                    None => html_escape::encode_safe(&msg.text),
+                                    Some(_) => ENCRYPTED_MESSAGE_TEXT.into(),
+                                };
                                 string_list.push_str(&format!("{escaped}<br>"));
                             }
                         }
@@ -38,7 +44,10 @@ pub fn view(req: SocketAddr, state: &State<TYRState>, jar: &CookieJar) -> RawHtm
                     for message in &user.messages {
                         if message.user_hash.is_none() {
                             // render all messages that this ip sent that don't have a hash.
-                            let escaped = html_escape::encode_safe(&message.text);
+                            let escaped = match message.ciphertext {
+                                None => html_escape::encode_safe(&message.text),
+                                Some(_) => ENCRYPTED_MESSAGE_TEXT.into(),
+                            };
                             string_list.push_str(&format!("{escaped}<br>"));
                         }
                     }
@@ -1,13 +1,70 @@
-use chrono::{DateTime, Local};
+use crate::highlight::normalize_language;
+use crate::pages::login::login_id;
+use crate::paste_counter::{PasteCounter, PasteTimestamp, PasteVisitors};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use chrono::{DateTime, Duration, Local};
+use reqwest::Url;
 use rocket::http::CookieJar;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 
+/// The length of a newly generated paste id, 62^8 ids leaves collisions very unlikely.
+pub static PASTE_ID_LENGTH: usize = 8;
+
+/// The number of colliding ids generated at one length before the id length is increased.
+pub static PASTE_ID_ATTEMPTS_PER_LENGTH: usize = 8;
+
+/// The length of the random token given to a poster for deleting their paste.
+pub static DELETION_TOKEN_LENGTH: usize = 32;
+
+/// The characters a generated paste id is made up of.
+static PASTE_ID_ALPHABET: &[u8; 62] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Generates a random base62 paste id of the given length.
+/// Ids are random rather than derived from the contents, so identical pastes from different users get different ids.
+pub fn random_paste_id(length: usize) -> String {
+    let mut id = String::with_capacity(length);
+    let mut bytes = [0u8; 32];
+    while id.len() < length {
+        OsRng.fill_bytes(&mut bytes);
+        for byte in bytes {
+            // bytes past the largest multiple of 62 are skipped, so every character is equally likely.
+            if (byte as usize) < PASTE_ID_ALPHABET.len() * 4 && id.len() < length {
+                id.push(PASTE_ID_ALPHABET[byte as usize % PASTE_ID_ALPHABET.len()] as char);
+            }
+        }
+    }
+    id
+}
+
+/// Inserts the paste under a newly generated id that is not already in use, returning the id.
+/// Pastes created before random ids keep their old hash ids, so their links stay valid.
+pub fn insert_new_paste(pastes: &mut HashMap<String, Paste>, paste: Paste) -> String {
+    let mut attempt = 0;
+    loop {
+        let paste_id = random_paste_id(PASTE_ID_LENGTH + attempt / PASTE_ID_ATTEMPTS_PER_LENGTH);
+        if !pastes.contains_key(&paste_id) {
+            pastes.insert(paste_id.clone(), paste);
+            return paste_id;
+        }
+        println!("Paste id collision on {paste_id}, generating another id.");
+        attempt += 1;
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum PasteContents {
     File(PathBuf),
     PlainText(String),
+    Redirect(String), // a short link, viewing the paste redirects to this url
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -18,14 +75,263 @@ pub struct Paste {
     pub ip_of_poster: String,
 
     // metrics of the paste, potentially will be used to allow old pastes or pastes with non-recent views or downloads to be culled.
-    pub view_count: u32,
-    pub download_count: u32,
-    pub time_of_last_download: DateTime<Local>,
-    pub time_of_last_view: DateTime<Local>,
+    // updated through atomics, so counting a view only needs a read lock on the paste map.
+    pub view_count: PasteCounter,
+    pub download_count: PasteCounter,
+    pub time_of_last_download: PasteTimestamp,
+    pub time_of_last_view: PasteTimestamp,
 
     // login cookie stored just in case we later want to allow a paste to be private and viewable only to specified hashes.
     pub login_cookie_of_poster: Option<String>,
     // potentially add a file upload optional field for this struct.
+
+    // lifetime of the paste chosen by the poster, the paste is deleted once either limit is reached.
+    pub expires_at: Option<DateTime<Local>>,
+    pub max_views: Option<u32>, // views and downloads both count towards this, burn after reading is a single view.
+    #[serde(default)]
+    pub expiry: Option<PasteExpiry>, // the expiry the poster chose, none for pastes that did not choose one
+
+    pub language: Option<String>, // language chosen by the poster for highlighting, detected when viewing if none.
+
+    #[serde(default)]
+    pub visibility: PasteVisibility,
+    #[serde(default)]
+    pub allowed_logins: Vec<String>, // login ids allowed to view a PrivateToLogins paste
+
+    pub password_hash: Option<String>, // argon2 hash of the password needed to view the paste, if the poster set one
+    pub deletion_token_hash: Option<String>, // sha256 hash of the token given to the poster for deleting the paste
+
+    #[serde(default)]
+    pub revisions: Vec<PasteRevision>, // previous versions of the paste, oldest first
+    pub last_edited: Option<DateTime<Local>>,
+
+    pub forked_from: Option<String>, // id of the paste this paste was forked from
+
+    // optional metadata given by the poster, shown alongside the paste and used to browse pastes.
+    pub title: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub listed_publicly: bool, // opted in by the poster to appear in the public paste directory
+
+    #[serde(default)]
+    pub visitors: PasteVisitors, // hashed ids of the visitors of the paste, only recorded when unique visitors are counted
+
+    #[serde(default)]
+    pub client_encrypted: bool, // the contents are ciphertext made in the posters browser, the key is only ever in the link they share
+}
+
+/// Returns the ids of the public pastes forked from the paste, oldest first.
+/// Forks that are not public are left out, so their ids are not given to viewers of the original.
+pub fn forks_of(pastes: &HashMap<String, Paste>, paste_id: &str) -> Vec<String> {
+    let mut forks = pastes
+        .iter()
+        .filter(|(_, paste)| {
+            paste.forked_from.as_deref() == Some(paste_id)
+                && paste.visibility == PasteVisibility::Public
+                && paste.password_hash.is_none()
+                && !paste.is_expired()
+        })
+        .map(|(id, paste)| (paste.post_time, id.clone()))
+        .collect::<Vec<_>>();
+    forks.sort();
+    forks.into_iter().map(|(_, id)| id).collect()
+}
+
+/// The maximum number of times a paste can be edited.
+pub static PASTE_REVISION_CAP: usize = 50;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A previous version of an edited paste.
+pub struct PasteRevision {
+    pub text: String,
+    pub posted_at: DateTime<Local>, // the time this version was posted, either the post time of the paste or the time of the edit
+}
+
+#[derive(FromFormField, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Who is allowed to view a paste, admins can always view every paste.
+pub enum PasteVisibility {
+    #[default]
+    Public,
+    Unlisted,        // viewable by anyone with the link, but never listed publicly
+    PrivateToLogin,  // viewable only by the login that posted it
+    PrivateToLogins, // viewable by the login that posted it, and the logins in its allowed list
+}
+
+impl PasteVisibility {
+    /// Parses the same names the form field accepts, used where the value does not come from a rocket form.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "public" => Some(PasteVisibility::Public),
+            "unlisted" => Some(PasteVisibility::Unlisted),
+            "privatetologin" => Some(PasteVisibility::PrivateToLogin),
+            "privatetologins" => Some(PasteVisibility::PrivateToLogins),
+            _ => None,
+        }
+    }
+}
+
+/// The maximum length of the title of a paste.
+pub static PASTE_TITLE_LENGTH_CAP: usize = 100;
+
+/// The maximum length of the description of a paste.
+pub static PASTE_DESCRIPTION_LENGTH_CAP: usize = 500;
+
+/// The maximum number of tags a paste can have.
+pub static PASTE_TAGS_CAP: usize = 10;
+
+/// The maximum length of a single tag.
+pub static TAG_LENGTH_CAP: usize = 32;
+
+/// Trims text given by a poster, discarding it if it is empty, and cutting it to the maximum length.
+fn normalize_text(text: Option<&str>, length_cap: usize) -> Option<String> {
+    text.map(|text| text.trim())
+        .filter(|text| !text.is_empty())
+        .map(|text| text.chars().take(length_cap).collect())
+}
+
+/// Splits tags given by a poster on commas and whitespace, tags are lowercase and may only contain letters, numbers, dashes and underscores.
+/// Invalid and repeated tags are dropped.
+pub fn normalize_tags(tags: &str) -> Vec<String> {
+    let mut normalized: Vec<String> = vec![];
+    for tag in tags.split(|c: char| c == ',' || c.is_whitespace()) {
+        let tag = tag.trim_start_matches('#').to_lowercase();
+        let is_valid = !tag.is_empty()
+            && tag.len() <= TAG_LENGTH_CAP
+            && tag
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        if is_valid && !normalized.contains(&tag) && normalized.len() < PASTE_TAGS_CAP {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+/// The maximum number of login ids a private paste can be shared with.
+pub static ALLOWED_LOGINS_CAP: usize = 32;
+
+/// The maximum length of the url a short link redirects to.
+pub static SHORT_LINK_URL_LENGTH_CAP: usize = 2000;
+
+/// Checks the url given for a short link, returning it if it is an http or https url.
+/// Other schemes such as javascript: or data: are refused, as a short link must only ever send the visitor to another page.
+pub fn short_link_url(url: &str) -> Option<String> {
+    let url = url.trim();
+    if url.len() > SHORT_LINK_URL_LENGTH_CAP {
+        return None;
+    }
+    Url::parse(url)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some())
+        .map(|url| url.to_string())
+}
+
+#[derive(FromForm, Debug, Clone, Default)]
+/// The options a poster can choose when creating a paste, shared between the paste form, and the upload routes.
+pub struct PasteOptions {
+    pub expiry: Option<PasteExpiry>,
+    pub max_views: Option<u32>,
+    pub burn_after_reading: bool,
+    pub language: Option<String>,
+    pub visibility: Option<PasteVisibility>,
+    pub allowed_logins: Option<String>, // login ids separated by commas or whitespace
+    pub password: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub tags: Option<String>, // tags separated by commas or whitespace
+    pub list_publicly: bool,
+}
+
+impl PasteOptions {
+    /// Applies the chosen options to a newly created paste.
+    /// Returns an error if the paste is private to its poster, but the poster is not logged in.
+    pub fn apply(&self, paste: Paste) -> Result<Paste, String> {
+        let mut paste = paste.with_lifetime(PasteLifetime {
+            expiry: self.expiry,
+            max_views: self.max_views,
+            burn_after_reading: self.burn_after_reading,
+        });
+        paste.language = normalize_language(self.language.as_deref());
+        paste.visibility = self.visibility.unwrap_or_default();
+        if paste.visibility == PasteVisibility::PrivateToLogins {
+            paste.allowed_logins = self
+                .allowed_logins
+                .as_deref()
+                .unwrap_or_default()
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|login| !login.is_empty())
+                .take(ALLOWED_LOGINS_CAP)
+                .map(|login| login.to_string())
+                .collect();
+        }
+
+        paste.title = normalize_text(self.title.as_deref(), PASTE_TITLE_LENGTH_CAP);
+        paste.description =
+            normalize_text(self.description.as_deref(), PASTE_DESCRIPTION_LENGTH_CAP);
+        paste.tags = normalize_tags(self.tags.as_deref().unwrap_or_default());
+
+        if let Some(password) = self
+            .password
+            .as_deref()
+            .filter(|password| !password.is_empty())
+        {
+            paste.set_password(password)?;
+        }
+
+        // only public pastes can be listed, unlisted and private pastes are never shown in listings.
+        paste.listed_publicly = self.list_publicly && paste.visibility == PasteVisibility::Public;
+
+        let is_private = matches!(
+            paste.visibility,
+            PasteVisibility::PrivateToLogin | PasteVisibility::PrivateToLogins
+        );
+        if is_private && paste.poster_login().is_none() {
+            return Err("You need to be logged in to create a private paste.".to_string());
+        }
+        Ok(paste)
+    }
+}
+
+#[derive(FromFormField, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+/// The choices a poster has for how long their paste lasts.
+pub enum PasteExpiry {
+    TenMinutes,
+    OneDay,
+    OneWeek,
+    Never,
+}
+
+impl PasteExpiry {
+    /// Returns the duration a paste lasts for, or none if it never expires.
+    pub fn duration(&self) -> Option<Duration> {
+        match self {
+            PasteExpiry::TenMinutes => Some(Duration::minutes(10)),
+            PasteExpiry::OneDay => Some(Duration::days(1)),
+            PasteExpiry::OneWeek => Some(Duration::weeks(1)),
+            PasteExpiry::Never => None,
+        }
+    }
+
+    /// Parses the same names the form field accepts, used where the value does not come from a rocket form.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "tenminutes" => Some(PasteExpiry::TenMinutes),
+            "oneday" => Some(PasteExpiry::OneDay),
+            "oneweek" => Some(PasteExpiry::OneWeek),
+            "never" => Some(PasteExpiry::Never),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// The lifetime a poster chose for their paste when creating it.
+pub struct PasteLifetime {
+    pub expiry: Option<PasteExpiry>,
+    pub max_views: Option<u32>,
+    pub burn_after_reading: bool,
 }
 
 impl Paste {
@@ -34,11 +340,59 @@ impl Paste {
             content: PasteContents::PlainText(text),
             post_time: Local::now(),
             ip_of_poster: req_socket.ip().to_string(),
-            view_count: 0,
-            download_count: 0,
-            time_of_last_download: Local::now(),
-            time_of_last_view: Local::now(),
+            view_count: Default::default(),
+            download_count: Default::default(),
+            time_of_last_download: PasteTimestamp::now(),
+            time_of_last_view: PasteTimestamp::now(),
             login_cookie_of_poster: { jar.get("login").map(|cookie| cookie.to_string()) },
+            expires_at: None,
+            max_views: None,
+            expiry: None,
+            language: None,
+            visibility: PasteVisibility::Public,
+            allowed_logins: vec![],
+            password_hash: None,
+            deletion_token_hash: None,
+            revisions: vec![],
+            last_edited: None,
+            forked_from: None,
+            title: None,
+            description: None,
+            tags: vec![],
+            listed_publicly: false,
+            visitors: Default::default(),
+            client_encrypted: false,
+        }
+    }
+    #[cfg(test)]
+    /// A text paste posted from 127.0.0.1 without a login, for tests to change the fields they need.
+    pub fn test_paste(text: &str) -> Self {
+        Paste {
+            content: PasteContents::PlainText(text.to_string()),
+            post_time: Local::now(),
+            ip_of_poster: "127.0.0.1".to_string(),
+            view_count: Default::default(),
+            download_count: Default::default(),
+            time_of_last_download: PasteTimestamp::now(),
+            time_of_last_view: PasteTimestamp::now(),
+            login_cookie_of_poster: None,
+            expires_at: None,
+            max_views: None,
+            expiry: None,
+            language: None,
+            visibility: PasteVisibility::Public,
+            allowed_logins: vec![],
+            password_hash: None,
+            deletion_token_hash: None,
+            revisions: vec![],
+            last_edited: None,
+            forked_from: None,
+            title: None,
+            description: None,
+            tags: vec![],
+            listed_publicly: false,
+            visitors: Default::default(),
+            client_encrypted: false,
         }
     }
     pub fn new_file_paste(file_path: PathBuf, req_socket: &SocketAddr, jar: &CookieJar) -> Self {
@@ -46,11 +400,28 @@ impl Paste {
             content: PasteContents::File(file_path),
             post_time: Local::now(),
             ip_of_poster: req_socket.ip().to_string(),
-            view_count: 0,
-            download_count: 0,
-            time_of_last_download: Local::now(),
-            time_of_last_view: Local::now(),
+            view_count: Default::default(),
+            download_count: Default::default(),
+            time_of_last_download: PasteTimestamp::now(),
+            time_of_last_view: PasteTimestamp::now(),
             login_cookie_of_poster: { jar.get("login").map(|cookie| cookie.to_string()) },
+            expires_at: None,
+            max_views: None,
+            expiry: None,
+            language: None,
+            visibility: PasteVisibility::Public,
+            allowed_logins: vec![],
+            password_hash: None,
+            deletion_token_hash: None,
+            revisions: vec![],
+            last_edited: None,
+            forked_from: None,
+            title: None,
+            description: None,
+            tags: vec![],
+            listed_publicly: false,
+            visitors: Default::default(),
+            client_encrypted: false,
         }
     }
     pub fn new_file_paste_with_date(
@@ -63,11 +434,400 @@ impl Paste {
             content: PasteContents::File(file_path),
             post_time: time,
             ip_of_poster: req_socket.ip().to_string(),
-            view_count: 0,
-            download_count: 0,
-            time_of_last_download: Local::now(),
-            time_of_last_view: Local::now(),
+            view_count: Default::default(),
+            download_count: Default::default(),
+            time_of_last_download: PasteTimestamp::now(),
+            time_of_last_view: PasteTimestamp::now(),
             login_cookie_of_poster: { jar.get("login").map(|cookie| cookie.to_string()) },
+            expires_at: None,
+            max_views: None,
+            expiry: None,
+            language: None,
+            visibility: PasteVisibility::Public,
+            allowed_logins: vec![],
+            password_hash: None,
+            deletion_token_hash: None,
+            revisions: vec![],
+            last_edited: None,
+            forked_from: None,
+            title: None,
+            description: None,
+            tags: vec![],
+            listed_publicly: false,
+            visitors: Default::default(),
+            client_encrypted: false,
+        }
+    }
+
+    /// Applies the lifetime chosen by the poster, burn after reading takes priority over a chosen view count.
+    pub fn with_lifetime(mut self, lifetime: PasteLifetime) -> Self {
+        self.expiry = lifetime.expiry;
+        self.expires_at = lifetime
+            .expiry
+            .and_then(|expiry| expiry.duration())
+            .map(|duration| self.post_time + duration);
+        self.max_views = match lifetime.burn_after_reading {
+            true => Some(1),
+            false => lifetime.max_views.filter(|max_views| *max_views > 0),
+        };
+        self
+    }
+
+    /// Returns true if the paste has outlived its expiry time, or used up all of its views.
+    pub fn is_expired(&self) -> bool {
+        let time_expired = self
+            .expires_at
+            .map(|expires_at| Local::now() >= expires_at)
+            .unwrap_or(false);
+        let views_used = self
+            .views_remaining()
+            .map(|remaining| remaining == 0)
+            .unwrap_or(false);
+        time_expired || views_used
+    }
+
+    /// Protects the paste with a password, storing only its argon2 hash.
+    pub fn set_password(&mut self, password: &str) -> Result<(), String> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|err| format!("Unable to hash paste password. {err}"))?;
+        self.password_hash = Some(hash.to_string());
+        Ok(())
+    }
+
+    /// Returns true if the password matches the one the poster set, always false if no password was set.
+    pub fn check_password(&self, password: &str) -> bool {
+        self.password_hash
+            .as_deref()
+            .and_then(|hash| PasswordHash::new(hash).ok())
+            .map(|hash| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &hash)
+                    .is_ok()
+            })
+            .unwrap_or(false)
+    }
+
+    /// Generates a new deletion token for the paste, storing only its hash, the token itself is returned to be given to the poster.
+    pub fn create_deletion_token(&mut self) -> String {
+        let token = random_paste_id(DELETION_TOKEN_LENGTH);
+        self.deletion_token_hash = Some(hex::encode(Sha256::digest(token.as_bytes())));
+        token
+    }
+
+    /// Returns true if the token is the deletion token given to the poster.
+    pub fn check_deletion_token(&self, token: &str) -> bool {
+        self.deletion_token_hash
+            .as_deref()
+            .map(|hash| hash == hex::encode(Sha256::digest(token.as_bytes())))
+            .unwrap_or(false)
+    }
+
+    /// Returns the current contents of the paste as text, or none if it is a file that is not text.
+    pub fn current_text(&self) -> Option<String> {
+        match &self.content {
+            PasteContents::PlainText(text) => Some(text.clone()),
+            PasteContents::File(path) => fs::read_to_string(path).ok(),
+            PasteContents::Redirect(_) => None,
+        }
+    }
+
+    /// Returns the number of versions of the paste, including the current one.
+    pub fn revision_count(&self) -> usize {
+        self.revisions.len() + 1
+    }
+
+    /// Returns the text of a version of the paste, where 0 is the original and the last revision is the current contents.
+    pub fn revision_text(&self, revision: usize) -> Option<String> {
+        match revision.cmp(&self.revisions.len()) {
+            Ordering::Less => Some(self.revisions[revision].text.clone()),
+            Ordering::Equal => self.current_text(),
+            Ordering::Greater => None,
+        }
+    }
+
+    /// Returns the time a version of the paste was posted.
+    pub fn revision_time(&self, revision: usize) -> Option<DateTime<Local>> {
+        match revision.cmp(&self.revisions.len()) {
+            Ordering::Less => Some(self.revisions[revision].posted_at),
+            Ordering::Equal => Some(self.last_edited.unwrap_or(self.post_time)),
+            Ordering::Greater => None,
+        }
+    }
+
+    /// Replaces the contents of the paste, keeping the previous contents as a revision.
+    /// File pastes have their file overwritten, so only file pastes that are text can be edited.
+    pub fn edit(&mut self, text: String) -> Result<(), String> {
+        if self.revisions.len() >= PASTE_REVISION_CAP {
+            return Err("This paste has been edited too many times.".to_string());
+        }
+        if self.client_encrypted {
+            return Err("Encrypted pastes can not be edited.".to_string());
+        }
+        let previous = self
+            .current_text()
+            .ok_or("This paste is not text, so it can not be edited.")?;
+        if previous == text {
+            return Err("The edit does not change the paste.".to_string());
+        }
+
+        match &self.content {
+            PasteContents::PlainText(_) => self.content = PasteContents::PlainText(text),
+            PasteContents::File(path) => fs::write(path, text)
+                .map_err(|err| format!("Unable to write edit to paste file. {err}"))?,
+            PasteContents::Redirect(_) => return Err("Short links can not be edited.".to_string()),
+        }
+
+        self.revisions.push(PasteRevision {
+            text: previous,
+            posted_at: self.last_edited.unwrap_or(self.post_time),
+        });
+        self.last_edited = Some(Local::now());
+        Ok(())
+    }
+
+    /// Returns the login hash of the poster, if they were logged in when posting.
+    pub fn poster_login(&self) -> Option<&str> {
+        self.login_cookie_of_poster
+            .as_deref()
+            .map(|cookie| cookie.strip_prefix("login=").unwrap_or(cookie))
+    }
+
+    /// Returns true if the paste was posted by the given login, or by the given ip when not logged in.
+    /// Pastes posted while logged in only belong to that login, not to the ip they were posted from.
+    pub fn is_posted_by(&self, login: Option<&str>, ip: &str) -> bool {
+        match login {
+            Some(login) => self.poster_login() == Some(login),
+            None => self.login_cookie_of_poster.is_none() && self.ip_of_poster == ip,
+        }
+    }
+
+    /// Returns true if a viewer with the given login hash may view this paste.
+    pub fn can_view(&self, viewer_login: Option<&str>, is_admin: bool) -> bool {
+        let is_poster = viewer_login.is_some() && viewer_login == self.poster_login();
+        let is_allowed = viewer_login
+            .map(|login| self.allowed_logins.contains(&login_id(login)))
+            .unwrap_or(false);
+        is_admin
+            || match self.visibility {
+                PasteVisibility::Public | PasteVisibility::Unlisted => true,
+                PasteVisibility::PrivateToLogin => is_poster,
+                PasteVisibility::PrivateToLogins => is_poster || is_allowed,
+            }
+    }
+
+    /// Returns the size of the contents of the paste in bytes.
+    pub fn size(&self) -> u64 {
+        match &self.content {
+            PasteContents::PlainText(text) | PasteContents::Redirect(text) => text.len() as u64,
+            PasteContents::File(path) => fs::metadata(path)
+                .map(|metadata| metadata.len())
+                .unwrap_or(0),
         }
     }
+
+    /// Returns the number of views and downloads left before the paste is deleted, or none if views are unlimited.
+    pub fn views_remaining(&self) -> Option<u32> {
+        self.max_views.map(|max_views| {
+            max_views.saturating_sub(
+                self.view_count
+                    .get()
+                    .saturating_add(self.download_count.get()),
+            )
+        })
+    }
+
+    /// Returns true if a view or download that brought the total to this count is within the view limit of the paste.
+    fn is_within_view_limit(&self, total: u32) -> bool {
+        self.max_views
+            .map(|max_views| total <= max_views)
+            .unwrap_or(true)
+    }
+
+    /// Counts a view of the paste, the visitor is only given when unique visitors are counted.
+    /// Returns false if the views of the paste were already used up by other viewers, in which case it must not be shown.
+    pub fn record_view(&self, visitor: Option<&str>) -> bool {
+        let views = self.view_count.increment();
+        self.time_of_last_view.set(Local::now());
+        if let Some(visitor) = visitor {
+            self.visitors.insert(visitor);
+        }
+        self.is_within_view_limit(views.saturating_add(self.download_count.get()))
+    }
+
+    /// Counts a download of the paste, the same as a view.
+    pub fn record_download(&self, visitor: Option<&str>) -> bool {
+        let downloads = self.download_count.increment();
+        self.time_of_last_download.set(Local::now());
+        if let Some(visitor) = visitor {
+            self.visitors.insert(visitor);
+        }
+        self.is_within_view_limit(downloads.saturating_add(self.view_count.get()))
+    }
+
+    /// Returns the view count shown to users, which is the number of unique visitors when they are counted.
+    pub fn displayed_views(&self, unique_visitors: bool) -> u32 {
+        match unique_visitors {
+            true => self.visitors.len() as u32,
+            false => self.view_count.get(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pages::login::login_id;
+    use crate::paste::{
+        insert_new_paste, normalize_tags, random_paste_id, short_link_url, Paste, PasteOptions,
+        PasteVisibility, PASTE_ID_LENGTH, PASTE_TAGS_CAP,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn paste_ids_are_unique() {
+        let id = random_paste_id(PASTE_ID_LENGTH);
+        assert_eq!(id.len(), PASTE_ID_LENGTH);
+        assert!(id.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_eq!(random_paste_id(40).len(), 40);
+
+        let paste = Paste::test_paste("lmao");
+
+        // identical pastes must not overwrite each other, and old style ids stay resolvable alongside new ones.
+        let mut pastes = HashMap::new();
+        pastes.insert("13124942130971219164".to_string(), paste.clone());
+        let first = insert_new_paste(&mut pastes, paste.clone());
+        let second = insert_new_paste(&mut pastes, paste);
+        assert_ne!(first, second);
+        assert_eq!(pastes.len(), 3);
+        assert!(pastes.contains_key("13124942130971219164"));
+    }
+
+    #[test]
+    fn private_paste_visibility() {
+        let paste = |login: Option<&str>| Paste {
+            login_cookie_of_poster: login.map(|login| format!("login={login}")),
+            ..Paste::test_paste("lmao")
+        };
+        let options = |visibility: PasteVisibility| PasteOptions {
+            visibility: Some(visibility),
+            allowed_logins: Some(format!("{}, {}", login_id("friend"), login_id("other"))),
+            ..Default::default()
+        };
+
+        assert!(options(PasteVisibility::PrivateToLogin)
+            .apply(paste(None))
+            .is_err());
+
+        let private = options(PasteVisibility::PrivateToLogin)
+            .apply(paste(Some("poster")))
+            .unwrap();
+        assert!(private.can_view(Some("poster"), false));
+        assert!(!private.can_view(Some("friend"), false));
+        assert!(!private.can_view(None, false));
+        assert!(private.can_view(None, true));
+
+        let shared = options(PasteVisibility::PrivateToLogins)
+            .apply(paste(Some("poster")))
+            .unwrap();
+        assert_eq!(shared.allowed_logins.len(), 2);
+        assert!(shared.can_view(Some("poster"), false));
+        assert!(shared.can_view(Some("friend"), false));
+        assert!(!shared.can_view(Some("stranger"), false));
+
+        let unlisted = options(PasteVisibility::Unlisted)
+            .apply(paste(None))
+            .unwrap();
+        assert!(unlisted.can_view(None, false));
+        assert!(unlisted.allowed_logins.is_empty());
+    }
+
+    #[test]
+    fn pastes_posted_by() {
+        let mut paste = Paste::test_paste("lmao");
+        assert!(paste.is_posted_by(None, "127.0.0.1"));
+        assert!(!paste.is_posted_by(None, "127.0.0.2"));
+        assert!(!paste.is_posted_by(Some("abc"), "127.0.0.1"));
+
+        paste.login_cookie_of_poster = Some("login=abc".to_string());
+        assert!(paste.is_posted_by(Some("abc"), "127.0.0.2"));
+        assert!(!paste.is_posted_by(Some("abd"), "127.0.0.1"));
+        assert!(!paste.is_posted_by(None, "127.0.0.1"));
+    }
+
+    #[test]
+    fn short_link_urls() {
+        assert_eq!(
+            short_link_url(" https://example.com/a?b=c "),
+            Some("https://example.com/a?b=c".to_string())
+        );
+        assert_eq!(
+            short_link_url("http://example.com"),
+            Some("http://example.com/".to_string())
+        );
+        assert_eq!(short_link_url("javascript:alert(1)"), None);
+        assert_eq!(short_link_url("data:text/html,hi"), None);
+        assert_eq!(short_link_url("example.com"), None);
+        assert_eq!(
+            short_link_url(&format!("https://example.com/{}", "a".repeat(2000))),
+            None
+        );
+    }
+
+    #[test]
+    fn tag_normalization() {
+        assert_eq!(
+            normalize_tags("Rust, #logs  rust,,bad/tag under_score"),
+            vec!["rust", "logs", "under_score"]
+        );
+        assert!(normalize_tags(" , ").is_empty());
+        let many = (0..20).map(|n| n.to_string()).collect::<Vec<_>>().join(",");
+        assert_eq!(normalize_tags(&many).len(), PASTE_TAGS_CAP);
+    }
+
+    #[test]
+    fn paste_passwords() {
+        let options = PasteOptions {
+            password: Some("hunter2".to_string()),
+            ..Default::default()
+        };
+        let paste = options.apply(Paste::test_paste("lmao")).unwrap();
+
+        assert!(!paste.password_hash.as_ref().unwrap().contains("hunter2"));
+        assert!(paste.check_password("hunter2"));
+        assert!(!paste.check_password("hunter3"));
+    }
+
+    #[test]
+    fn paste_edits_keep_revisions() {
+        let mut paste = Paste::test_paste("one");
+
+        assert!(paste.edit("one".to_string()).is_err());
+        paste.edit("two".to_string()).unwrap();
+        paste.edit("three".to_string()).unwrap();
+
+        assert_eq!(paste.revision_count(), 3);
+        assert_eq!(paste.revision_text(0), Some("one".to_string()));
+        assert_eq!(paste.revision_text(1), Some("two".to_string()));
+        assert_eq!(paste.revision_text(2), Some("three".to_string()));
+        assert_eq!(paste.revision_text(3), None);
+        assert_eq!(paste.current_text(), Some("three".to_string()));
+    }
+
+    #[test]
+    fn view_limits_hold_across_views_and_downloads() {
+        let paste = Paste {
+            max_views: Some(2),
+            ..Paste::test_paste("limited")
+        };
+
+        assert!(paste.record_view(Some("a")));
+        assert!(paste.record_download(Some("a")));
+        assert_eq!(paste.views_remaining(), Some(0));
+        // a viewer that was let in before the paste was deleted still must not see it.
+        assert!(!paste.record_view(Some("b")));
+        assert_eq!(paste.displayed_views(false), 2);
+        assert_eq!(paste.displayed_views(true), 2);
+        assert_eq!(paste.download_count.get(), 1);
+    }
 }
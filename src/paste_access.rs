@@ -0,0 +1,159 @@
+use crate::state_management::TYRState;
+use chrono::Local;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use rocket::http::{Cookie, CookieJar, SameSite};
+use rocket::time::Duration;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+
+/// The duration in seconds a correct paste password grants access to that paste for.
+pub static PASTE_ACCESS_DURATION: i64 = 600;
+
+/// The number of wrong paste passwords an ip can give before every password it gives is refused.
+pub static PASSWORD_ATTEMPTS_CAP: u32 = 5;
+
+/// The duration in seconds wrong paste passwords are counted against an ip for.
+pub static PASSWORD_ATTEMPT_WINDOW: i64 = 300;
+
+#[derive(Debug, Clone, Copy)]
+/// The wrong paste passwords an ip has given, counted from the first wrong password in the window.
+pub struct PasswordAttempts {
+    pub failures: u32,
+    pub window_start: i64,
+}
+
+lazy_static! {
+    /// Key used to sign paste access cookies, regenerated each launch so a restart revokes all access cookies.
+    static ref ACCESS_KEY: Vec<u8> = [uuid::Uuid::new_v4(), uuid::Uuid::new_v4()]
+        .iter()
+        .flat_map(|uuid| uuid.into_bytes())
+        .collect();
+}
+
+/// Returns the name of the access cookie for a paste.
+/// Custom urls can contain characters not allowed in a cookie name, so the name is derived from a hash of the paste id.
+pub fn access_cookie_name(paste_id: &str) -> String {
+    let mut name = hex::encode(Sha256::digest(paste_id.as_bytes()));
+    name.truncate(16);
+    format!("paste_access_{name}")
+}
+
+/// Signs the paste id and the time the access expires, so the cookie can not be forged or moved to another paste.
+fn access_mac(paste_id: &str, expires_at: i64) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(&ACCESS_KEY).expect("HMAC can take a key of any size");
+    mac.update(format!("{paste_id}:{expires_at}").as_bytes());
+    mac
+}
+
+/// Returns true if the hex signature is valid for the paste id and expiry time.
+fn verify_access(paste_id: &str, expires_at: i64, signature: &str) -> bool {
+    hex::decode(signature)
+        .map(|signature| {
+            access_mac(paste_id, expires_at)
+                .verify_slice(&signature)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+/// Gives the user a short lived cookie that grants access to the password protected paste.
+pub fn grant_access(jar: &CookieJar, paste_id: &str) {
+    let expires_at = Local::now().timestamp() + PASTE_ACCESS_DURATION;
+    let signature = hex::encode(access_mac(paste_id, expires_at).finalize().into_bytes());
+    let value = format!("{expires_at}:{signature}");
+    let cookie = Cookie::build((access_cookie_name(paste_id), value))
+        .path("/paste")
+        .max_age(Duration::seconds(PASTE_ACCESS_DURATION))
+        .http_only(true)
+        .same_site(SameSite::Lax);
+    jar.add(cookie);
+}
+
+/// Returns true if the user has an unexpired access cookie for this paste.
+pub fn has_access(jar: &CookieJar, paste_id: &str) -> bool {
+    jar.get(&access_cookie_name(paste_id))
+        .and_then(|cookie| {
+            let (expires_at, signature) = cookie.value().split_once(':')?;
+            let expires_at = expires_at.parse::<i64>().ok()?;
+            let is_valid = expires_at > Local::now().timestamp()
+                && verify_access(paste_id, expires_at, signature);
+            Some(is_valid)
+        })
+        .unwrap_or(false)
+}
+
+/// Checks a password given for a paste, counting wrong passwords against the ip that gave them.
+/// Once an ip has given too many wrong passwords, every password it gives is refused until the window passes.
+pub fn try_paste_password(state: &TYRState, ip: IpAddr, paste_id: &str, password: &str) -> bool {
+    let now = Local::now().timestamp();
+    let ip = ip.to_string();
+    let mut attempts = state.password_attempts.write().unwrap();
+    attempts.retain(|_, attempt| now - attempt.window_start < PASSWORD_ATTEMPT_WINDOW);
+    if let Some(attempt) = attempts.get(&ip) {
+        if attempt.failures >= PASSWORD_ATTEMPTS_CAP {
+            return false;
+        }
+    }
+
+    let is_correct = state
+        .pastes
+        .read()
+        .unwrap()
+        .get(paste_id)
+        .map(|paste| paste.check_password(password))
+        .unwrap_or(false);
+    if !is_correct {
+        attempts
+            .entry(ip)
+            .or_insert(PasswordAttempts {
+                failures: 0,
+                window_start: now,
+            })
+            .failures += 1;
+    }
+    is_correct
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paste::Paste;
+    use crate::paste_access::{
+        access_mac, try_paste_password, verify_access, PASSWORD_ATTEMPTS_CAP, PASTE_ACCESS_DURATION,
+    };
+    use crate::state_management::TYRState;
+    use chrono::Local;
+    use hmac::Mac;
+
+    #[test]
+    fn access_is_scoped_to_paste() {
+        let expires_at = Local::now().timestamp() + PASTE_ACCESS_DURATION;
+        let signature = hex::encode(access_mac("abc", expires_at).finalize().into_bytes());
+        assert!(verify_access("abc", expires_at, &signature));
+        assert!(!verify_access("abd", expires_at, &signature));
+        assert!(!verify_access("abc", expires_at + 1, &signature));
+        assert!(!verify_access("abc", expires_at, "not hex"));
+    }
+
+    #[test]
+    fn wrong_passwords_are_limited_per_ip() {
+        let state = TYRState::default();
+        let mut paste = Paste::test_paste("a protected paste");
+        paste.set_password("hunter2").unwrap();
+        state
+            .pastes
+            .write()
+            .unwrap()
+            .insert("abc".to_string(), paste);
+        let guesser = "1.2.3.4".parse().unwrap();
+        let owner = "5.6.7.8".parse().unwrap();
+
+        assert!(try_paste_password(&state, guesser, "abc", "hunter2"));
+        for _ in 0..PASSWORD_ATTEMPTS_CAP {
+            assert!(!try_paste_password(&state, guesser, "abc", "hunter3"));
+        }
+        assert!(!try_paste_password(&state, guesser, "abc", "hunter2"));
+        assert!(try_paste_password(&state, owner, "abc", "hunter2"));
+    }
+}
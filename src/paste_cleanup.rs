@@ -0,0 +1,440 @@
+use crate::paste::{Paste, PasteContents, PasteExpiry};
+use crate::state_management::{save_program_state, TYRState};
+use chrono::{DateTime, Local};
+use rocket::tokio::time::sleep;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// The duration in seconds the paste sweeper waits between each check for expired pastes.
+pub static PASTE_SWEEP_INTERVAL: u64 = 60;
+
+/// The directory uploaded files are stored in, each upload is in a folder named after its upload date.
+pub static FILE_UPLOAD_DIRECTORY: &str = "./output/file_uploads/";
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+/// Thresholds for the periodic cleanup of stale pastes, read from the [<profile>.paste_cleanup] table of Rocket.toml.
+/// Both thresholds are off unless configured, so no paste is deleted as stale without the host asking for it.
+pub struct CleanupConfig {
+    // thresholds that are unset, or of zero or less, are disabled.
+    pub max_age_days: Option<i64>, // pastes older than this are deleted no matter how active they are
+    pub max_inactive_days: Option<i64>, // pastes not viewed or downloaded for this long are deleted
+    pub interval_minutes: u64, // also how old an unreferenced upload must be before it is deleted, so uploads in progress are kept
+}
+
+impl Default for CleanupConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: None,
+            max_inactive_days: None,
+            interval_minutes: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// The outcome of a single cleanup run, shown in the admin panel.
+pub struct CleanupReport {
+    pub time: DateTime<Local>,
+    pub stale_pastes: Vec<String>,
+    pub missing_file_pastes: Vec<String>, // file pastes whose file no longer exists on disk
+    pub orphaned_files: Vec<PathBuf>,     // files on disk that no paste refers to
+    pub removed_folders: Vec<PathBuf>,
+    pub errors: Vec<String>,
+}
+
+impl CleanupReport {
+    /// Returns true if the cleanup run changed the program state, and so it should be saved.
+    pub fn removed_pastes(&self) -> bool {
+        !self.stale_pastes.is_empty() || !self.missing_file_pastes.is_empty()
+    }
+}
+
+/// Removes a paste from the program state, and deletes the file backing it if it has one.
+pub fn delete_paste(state: &TYRState, paste_id: &str) -> Option<Paste> {
+    let paste = state.pastes.write().unwrap().remove(paste_id)?;
+    if let PasteContents::File(path) = &paste.content {
+        if let Err(err) = fs::remove_file(path) {
+            println!("Unable to remove file of paste {paste_id}. {err}");
+        }
+    }
+    Some(paste)
+}
+
+/// Deletes every paste that is past its expiry time or out of views, returning the ids of the deleted pastes.
+pub fn remove_expired_pastes(state: &TYRState) -> Vec<String> {
+    let expired = state
+        .pastes
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(_, paste)| paste.is_expired())
+        .map(|(paste_id, _)| paste_id.clone())
+        .collect::<Vec<String>>();
+
+    for paste_id in &expired {
+        delete_paste(state, paste_id);
+    }
+
+    expired
+}
+
+/// Background task that periodically deletes expired pastes, saving the program state when any were deleted.
+pub async fn paste_sweeper(state: TYRState) {
+    loop {
+        sleep(Duration::from_secs(PASTE_SWEEP_INTERVAL)).await;
+
+        let expired = remove_expired_pastes(&state);
+        if !expired.is_empty() {
+            println!("Deleted expired pastes: {:?}", expired);
+            save_program_state((&state).into(), &PathBuf::from("./output/state.ser"));
+        }
+    }
+}
+
+/// Returns true if the paste is older than the configured max age, or has not been viewed or downloaded within the configured inactivity window.
+/// Pastes their poster chose to never expire are never stale.
+pub fn is_stale(paste: &Paste, config: &CleanupConfig, now: DateTime<Local>) -> bool {
+    if paste.expiry == Some(PasteExpiry::Never) {
+        return false;
+    }
+    let too_old = config
+        .max_age_days
+        .filter(|days| *days > 0)
+        .map(|days| now - paste.post_time > chrono::Duration::days(days))
+        .unwrap_or(false);
+    let last_activity = paste
+        .post_time
+        .max(paste.time_of_last_view.get())
+        .max(paste.time_of_last_download.get());
+    let inactive = config
+        .max_inactive_days
+        .filter(|days| *days > 0)
+        .map(|days| now - last_activity > chrono::Duration::days(days))
+        .unwrap_or(false);
+    too_old || inactive
+}
+
+/// Deletes stale pastes, then reconciles the upload directory against the remaining pastes.
+/// File pastes whose file is missing are removed, and files or folders no paste refers to are deleted once they are older than one interval.
+pub fn run_cleanup(
+    state: &TYRState,
+    config: &CleanupConfig,
+    upload_directory: &Path,
+) -> CleanupReport {
+    let now = Local::now();
+    let mut report = CleanupReport {
+        time: now,
+        ..Default::default()
+    };
+
+    report.stale_pastes = state
+        .pastes
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(_, paste)| is_stale(paste, config, now))
+        .map(|(paste_id, _)| paste_id.clone())
+        .collect();
+    for paste_id in &report.stale_pastes {
+        delete_paste(state, paste_id);
+    }
+
+    let mut referenced_files = HashSet::new();
+    let mut unchecked_folders = HashSet::new();
+    {
+        let mut lock = state.pastes.write().unwrap();
+        for (paste_id, paste) in lock.iter() {
+            if let PasteContents::File(path) = &paste.content {
+                match fs::canonicalize(path) {
+                    Ok(path) => {
+                        referenced_files.insert(path);
+                    }
+                    Err(err) if err.kind() == ErrorKind::NotFound => {
+                        report.missing_file_pastes.push(paste_id.clone())
+                    }
+                    // the file may still be there, so the paste is kept and its folder is left alone.
+                    Err(err) => {
+                        let error = format!(
+                            "Unable to check the file of paste {paste_id}, skipping it. {err}"
+                        );
+                        println!("{error}");
+                        report.errors.push(error);
+                        if let Some(folder) = path.parent() {
+                            unchecked_folders.insert(folder.to_path_buf());
+                        }
+                    }
+                }
+            }
+        }
+        for paste_id in &report.missing_file_pastes {
+            lock.remove(paste_id);
+        }
+    }
+
+    let grace_period = Duration::from_secs(config.interval_minutes * 60);
+    remove_orphans(
+        upload_directory,
+        &referenced_files,
+        &unchecked_folders,
+        grace_period,
+        &mut report,
+    );
+
+    report
+}
+
+/// Returns true if the file or folder was last modified longer ago than the grace period.
+/// Uploads are written before their paste is saved, so recent files are kept even if no paste refers to them yet.
+fn is_older_than(path: &Path, grace_period: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map(|modified| {
+            SystemTime::now()
+                .duration_since(modified)
+                .map(|age| age >= grace_period)
+                .unwrap_or(false)
+        })
+        .unwrap_or(false)
+}
+
+/// Recursively deletes files in the directory that are not referenced by a paste, and the folders left empty by doing so.
+/// Files and folders newer than the grace period, and folders holding a file that could not be checked, are kept.
+fn remove_orphans(
+    directory: &Path,
+    referenced_files: &HashSet<PathBuf>,
+    unchecked_folders: &HashSet<PathBuf>,
+    grace_period: Duration,
+    report: &mut CleanupReport,
+) {
+    if unchecked_folders.contains(directory) {
+        return;
+    }
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) => {
+            report
+                .errors
+                .push(format!("Unable to read {}. {err}", directory.display()));
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            remove_orphans(
+                &path,
+                referenced_files,
+                unchecked_folders,
+                grace_period,
+                report,
+            );
+            let is_empty = fs::read_dir(&path)
+                .map(|mut entries| entries.next().is_none())
+                .unwrap_or(false);
+            if is_empty && is_older_than(&path, grace_period) {
+                match fs::remove_dir(&path) {
+                    Ok(_) => report.removed_folders.push(path),
+                    Err(err) => report
+                        .errors
+                        .push(format!("Unable to remove {}. {err}", path.display())),
+                }
+            }
+        } else {
+            let is_referenced = match fs::canonicalize(&path) {
+                Ok(path) => referenced_files.contains(&path),
+                Err(err) => {
+                    report.errors.push(format!(
+                        "Unable to check {}, skipping it. {err}",
+                        path.display()
+                    ));
+                    continue;
+                }
+            };
+            if !is_referenced && is_older_than(&path, grace_period) {
+                match fs::remove_file(&path) {
+                    Ok(_) => report.orphaned_files.push(path),
+                    Err(err) => report
+                        .errors
+                        .push(format!("Unable to remove {}. {err}", path.display())),
+                }
+            }
+        }
+    }
+}
+
+/// Runs a cleanup with the configured thresholds, storing the report for the admin panel and saving the program state if pastes were removed.
+pub fn cleanup_now(state: &TYRState) -> CleanupReport {
+    let config = state.cleanup_config.read().unwrap().clone();
+    let report = run_cleanup(state, &config, Path::new(FILE_UPLOAD_DIRECTORY));
+    if report.removed_pastes() {
+        save_program_state(state.into(), &PathBuf::from("./output/state.ser"));
+    }
+    *state.cleanup_report.write().unwrap() = Some(report.clone());
+    report
+}
+
+/// Background task that periodically removes stale pastes and orphaned uploads.
+pub async fn cleanup_worker(state: TYRState) {
+    loop {
+        let interval = state.cleanup_config.read().unwrap().interval_minutes.max(1);
+        sleep(Duration::from_secs(interval * 60)).await;
+
+        let report = cleanup_now(&state);
+        println!(
+            "Cleanup removed {} stale pastes, {} pastes with missing files, {} orphaned files, {} folders.",
+            report.stale_pastes.len(),
+            report.missing_file_pastes.len(),
+            report.orphaned_files.len(),
+            report.removed_folders.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paste::{Paste, PasteContents, PasteExpiry, PasteLifetime};
+    use crate::paste_cleanup::{remove_expired_pastes, run_cleanup, CleanupConfig};
+    use crate::paste_counter::{PasteCounter, PasteTimestamp};
+    use crate::state_management::TYRState;
+    use chrono::{Duration, Local};
+    use std::fs;
+
+    fn text_paste(lifetime: PasteLifetime) -> Paste {
+        Paste::test_paste("lmao").with_lifetime(lifetime)
+    }
+
+    #[test]
+    fn expired_pastes_are_removed() {
+        let state = TYRState::default();
+
+        let mut old = text_paste(PasteLifetime {
+            expiry: Some(PasteExpiry::TenMinutes),
+            ..Default::default()
+        });
+        old.expires_at = Some(Local::now() - Duration::seconds(1));
+
+        let mut burnt = text_paste(PasteLifetime {
+            burn_after_reading: true,
+            max_views: Some(10),
+            ..Default::default()
+        });
+        assert_eq!(burnt.views_remaining(), Some(1));
+        burnt.view_count = PasteCounter::new(1);
+
+        let mut limited = text_paste(PasteLifetime {
+            expiry: Some(PasteExpiry::OneWeek),
+            max_views: Some(3),
+            ..Default::default()
+        });
+        limited.view_count = PasteCounter::new(1);
+        limited.download_count = PasteCounter::new(1);
+        assert_eq!(limited.views_remaining(), Some(1));
+
+        {
+            let mut pastes = state.pastes.write().unwrap();
+            pastes.insert("old".to_string(), old);
+            pastes.insert("burnt".to_string(), burnt);
+            pastes.insert("limited".to_string(), limited);
+            pastes.insert("forever".to_string(), text_paste(Default::default()));
+        }
+
+        let mut removed = remove_expired_pastes(&state);
+        removed.sort();
+        assert_eq!(removed, vec!["burnt".to_string(), "old".to_string()]);
+
+        let pastes = state.pastes.read().unwrap();
+        assert!(pastes.contains_key("limited"));
+        assert!(pastes.contains_key("forever"));
+    }
+
+    #[test]
+    fn cleanup_removes_stale_pastes_and_orphans() {
+        let state = TYRState::default();
+        let upload_directory = std::env::temp_dir().join(format!(
+            "tyr_cleanup_test_{}",
+            uuid::Uuid::new_v4().as_simple()
+        ));
+        let kept_folder = upload_directory.join("2023-1-1");
+        let orphan_folder = upload_directory.join("2023-1-2");
+        fs::create_dir_all(&kept_folder).unwrap();
+        fs::create_dir_all(&orphan_folder).unwrap();
+        let kept_file = kept_folder.join("kept.txt");
+        fs::write(&kept_file, "kept").unwrap();
+        fs::write(kept_folder.join("orphan.txt"), "orphan").unwrap();
+        fs::write(orphan_folder.join("orphan.txt"), "orphan").unwrap();
+
+        let mut file_paste = text_paste(Default::default());
+        file_paste.content = PasteContents::File(kept_file.clone());
+        let mut missing_file_paste = text_paste(Default::default());
+        missing_file_paste.content = PasteContents::File(kept_folder.join("missing.txt"));
+        let mut stale = text_paste(Default::default());
+        stale.post_time = Local::now() - Duration::days(40);
+        stale.time_of_last_view = PasteTimestamp::new(Local::now() - Duration::days(35));
+        stale.time_of_last_download = PasteTimestamp::new(Local::now() - Duration::days(40));
+        let mut old_but_active = text_paste(Default::default());
+        old_but_active.post_time = Local::now() - Duration::days(40);
+        let mut kept_forever = text_paste(PasteLifetime {
+            expiry: Some(PasteExpiry::Never),
+            ..Default::default()
+        });
+        kept_forever.post_time = stale.post_time;
+        kept_forever.time_of_last_view = PasteTimestamp::new(stale.post_time);
+        kept_forever.time_of_last_download = PasteTimestamp::new(stale.post_time);
+
+        {
+            let mut pastes = state.pastes.write().unwrap();
+            pastes.insert("file".to_string(), file_paste);
+            pastes.insert("missing".to_string(), missing_file_paste);
+            pastes.insert("stale".to_string(), stale);
+            pastes.insert("old_but_active".to_string(), old_but_active);
+            pastes.insert("kept_forever".to_string(), kept_forever);
+        }
+
+        // by default no paste is stale, and files are too new to be orphans.
+        let report = run_cleanup(&state, &CleanupConfig::default(), &upload_directory);
+        assert!(report.stale_pastes.is_empty());
+        assert_eq!(report.missing_file_pastes, vec!["missing".to_string()]);
+        assert!(report.orphaned_files.is_empty());
+        assert!(report.removed_folders.is_empty());
+
+        let config = CleanupConfig {
+            max_inactive_days: Some(30),
+            interval_minutes: 0,
+            ..Default::default()
+        };
+        let report = run_cleanup(&state, &config, &upload_directory);
+
+        assert_eq!(report.stale_pastes, vec!["stale".to_string()]);
+        assert!(report.missing_file_pastes.is_empty());
+        assert_eq!(report.orphaned_files.len(), 2);
+        assert_eq!(report.removed_folders, vec![orphan_folder.clone()]);
+        assert!(report.errors.is_empty());
+        assert!(kept_file.exists());
+        assert!(!orphan_folder.exists());
+
+        let mut remaining = state
+            .pastes
+            .read()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>();
+        remaining.sort();
+        assert_eq!(
+            remaining,
+            vec![
+                "file".to_string(),
+                "kept_forever".to_string(),
+                "old_but_active".to_string()
+            ]
+        );
+
+        fs::remove_dir_all(&upload_directory).unwrap();
+    }
+}
@@ -0,0 +1,206 @@
+use crate::pages::login::SALT;
+use chrono::{DateTime, Local, TimeZone};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering};
+use std::sync::Mutex;
+
+/// The maximum number of unique visitors remembered for a single paste, visitors past this are no longer counted as unique.
+pub static PASTE_VISITORS_CAP: usize = 10_000;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+/// Settings for counting paste views, read from the "paste_counters" table of Rocket.toml.
+pub struct CounterConfig {
+    #[serde(default)]
+    pub unique_visitors: bool, // show the number of unique visitors of a paste instead of its raw view count
+}
+
+#[derive(Debug, Default)]
+/// A counter of a paste that can be incremented while only holding a read lock on the paste map.
+/// Saved as a plain number, so state saves from before counters were atomic still load.
+pub struct PasteCounter(AtomicU32);
+
+impl PasteCounter {
+    pub fn new(count: u32) -> Self {
+        PasteCounter(AtomicU32::new(count))
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Adds one to the counter, returning the new count.
+    pub fn increment(&self) -> u32 {
+        self.0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                Some(count.saturating_add(1))
+            })
+            .unwrap_or_default()
+            .saturating_add(1)
+    }
+}
+
+impl Clone for PasteCounter {
+    fn clone(&self) -> Self {
+        PasteCounter::new(self.get())
+    }
+}
+
+impl fmt::Display for PasteCounter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.get())
+    }
+}
+
+impl Serialize for PasteCounter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.get())
+    }
+}
+
+impl<'de> Deserialize<'de> for PasteCounter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u32::deserialize(deserializer).map(PasteCounter::new)
+    }
+}
+
+#[derive(Debug)]
+/// A time of a paste that can be updated while only holding a read lock on the paste map.
+/// Saved as a date, so state saves from before times were atomic still load.
+pub struct PasteTimestamp(AtomicI64); // milliseconds since the unix epoch
+
+impl PasteTimestamp {
+    pub fn new(time: DateTime<Local>) -> Self {
+        PasteTimestamp(AtomicI64::new(time.timestamp_millis()))
+    }
+
+    pub fn now() -> Self {
+        PasteTimestamp::new(Local::now())
+    }
+
+    pub fn get(&self) -> DateTime<Local> {
+        Local
+            .timestamp_millis_opt(self.0.load(Ordering::Relaxed))
+            .single()
+            .unwrap_or_default()
+    }
+
+    pub fn set(&self, time: DateTime<Local>) {
+        self.0.store(time.timestamp_millis(), Ordering::Relaxed);
+    }
+}
+
+impl Clone for PasteTimestamp {
+    fn clone(&self) -> Self {
+        PasteTimestamp::new(self.get())
+    }
+}
+
+impl fmt::Display for PasteTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.get())
+    }
+}
+
+impl Serialize for PasteTimestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.get().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PasteTimestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        DateTime::<Local>::deserialize(deserializer).map(PasteTimestamp::new)
+    }
+}
+
+#[derive(Debug, Default)]
+/// The hashed ids of the visitors of a paste, only recorded when unique visitors are counted.
+pub struct PasteVisitors(Mutex<HashSet<String>>);
+
+impl PasteVisitors {
+    /// Records the visitor, returning true if they had not visited before.
+    pub fn insert(&self, visitor: &str) -> bool {
+        let mut visitors = self.0.lock().unwrap();
+        if visitors.len() >= PASTE_VISITORS_CAP {
+            return false;
+        }
+        visitors.insert(visitor.to_string())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+impl Clone for PasteVisitors {
+    fn clone(&self) -> Self {
+        PasteVisitors(Mutex::new(self.0.lock().unwrap().clone()))
+    }
+}
+
+impl Serialize for PasteVisitors {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.lock().unwrap().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PasteVisitors {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashSet::<String>::deserialize(deserializer)
+            .map(|visitors| PasteVisitors(Mutex::new(visitors)))
+    }
+}
+
+/// Returns the id a visitor is remembered by, a hash of their login, or of their ip when not logged in.
+/// The hash is keyed with the server salt, so without it the ips of visitors can not be found in the save file by hashing every ip.
+pub fn visitor_id(login: Option<&str>, ip: &str) -> String {
+    keyed_visitor_id(SALT.as_bytes(), login, ip)
+}
+
+/// Hashes the login or ip of a visitor with the key.
+fn keyed_visitor_id(key: &[u8], login: Option<&str>, ip: &str) -> String {
+    let visitor = match login {
+        Some(login) => format!("login:{login}"),
+        None => format!("ip:{ip}"),
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(visitor.as_bytes());
+    let mut id = hex::encode(mac.finalize().into_bytes());
+    id.truncate(32);
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paste_counter::{keyed_visitor_id, PasteCounter, PasteTimestamp, PasteVisitors};
+    use chrono::Local;
+
+    #[test]
+    fn counters_save_as_plain_values() {
+        let counter = PasteCounter::new(u32::MAX - 1);
+        assert_eq!(counter.increment(), u32::MAX);
+        assert_eq!(counter.increment(), u32::MAX);
+        assert_eq!(serde_json::to_string(&PasteCounter::new(7)).unwrap(), "7");
+        assert_eq!(serde_json::from_str::<PasteCounter>("7").unwrap().get(), 7);
+
+        let now = Local::now();
+        let saved = serde_json::to_string(&now).unwrap();
+        let timestamp = serde_json::from_str::<PasteTimestamp>(&saved).unwrap();
+        assert_eq!(timestamp.get().timestamp_millis(), now.timestamp_millis());
+
+        let visitors = PasteVisitors::default();
+        assert!(visitors.insert(&keyed_visitor_id(b"salt", None, "127.0.0.1")));
+        assert!(!visitors.insert(&keyed_visitor_id(b"salt", None, "127.0.0.1")));
+        assert!(visitors.insert(&keyed_visitor_id(b"salt", Some("127.0.0.1"), "127.0.0.1")));
+        assert_eq!(visitors.len(), 2);
+        // without the key, the id of an ip can not be worked out.
+        assert_ne!(
+            keyed_visitor_id(b"salt", None, "127.0.0.1"),
+            keyed_visitor_id(b"other salt", None, "127.0.0.1")
+        );
+    }
+}
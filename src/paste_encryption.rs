@@ -0,0 +1,120 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use maud::{html, Markup, PreEscaped};
+
+/// The length of the random iv the browser puts in front of the ciphertext of an encrypted paste.
+pub static ENCRYPTED_PASTE_IV_LENGTH: usize = 12;
+
+/// The length of the authentication tag at the end of the ciphertext of an encrypted paste.
+pub static ENCRYPTED_PASTE_TAG_LENGTH: usize = 16;
+
+/// Returns true if the text is what the browser sends for an encrypted paste, the base64 of the iv followed by the AES-GCM ciphertext.
+/// The server can not check that it decrypts, only that it is shaped like ciphertext, so plain text is not stored as "encrypted" by mistake.
+pub fn is_valid_ciphertext(text: &str) -> bool {
+    STANDARD
+        .decode(text.trim())
+        .map(|bytes| bytes.len() >= ENCRYPTED_PASTE_IV_LENGTH + ENCRYPTED_PASTE_TAG_LENGTH)
+        .unwrap_or(false)
+}
+
+/// Returns the length in bytes of the text the browser encrypted, as AES-GCM ciphertext is as long as its plaintext plus the iv and tag.
+/// Used to hold encrypted pastes to the same length limits as plain ones, rather than the longer base64.
+pub fn plaintext_length(text: &str) -> Option<usize> {
+    STANDARD
+        .decode(text.trim())
+        .ok()?
+        .len()
+        .checked_sub(ENCRYPTED_PASTE_IV_LENGTH + ENCRYPTED_PASTE_TAG_LENGTH)
+}
+
+/// The checkbox for encrypting a paste in the browser before it is posted, placed inside the paste form.
+/// The key is put in the fragment of the form action, which the browser keeps through the redirect to the new paste, but never sends to the server.
+pub fn encrypt_in_browser_input() -> Markup {
+    html! {
+        input type="checkbox" name="client_encrypted" id="client_encrypted";
+        label for="client_encrypted" {"Encrypt in browser, the server never sees the text and only the link holds the key (needs https)"}
+        br;
+        (PreEscaped(r##"<script>
+        function toBase64(bytes) {
+            let binary = "";
+            bytes.forEach((byte) => binary += String.fromCharCode(byte));
+            return btoa(binary);
+        }
+
+        const encryptBox = document.getElementById("client_encrypted");
+        encryptBox.form.addEventListener("submit", async (event) => {
+            if (!encryptBox.checked) {
+                return;
+            }
+            event.preventDefault();
+            const form = event.target;
+            const text = document.getElementById("text");
+            const key = await crypto.subtle.generateKey({ name: "AES-GCM", length: 256 }, true, ["encrypt"]);
+            const iv = crypto.getRandomValues(new Uint8Array(12));
+            const ciphertext = new Uint8Array(await crypto.subtle.encrypt({ name: "AES-GCM", iv: iv }, key, new TextEncoder().encode(text.value)));
+            const data = new Uint8Array(iv.length + ciphertext.length);
+            data.set(iv);
+            data.set(ciphertext, iv.length);
+            text.value = toBase64(data);
+
+            const rawKey = new Uint8Array(await crypto.subtle.exportKey("raw", key));
+            const keyText = toBase64(rawKey).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+            form.action = form.getAttribute("action").split("#")[0] + "#" + keyText;
+            form.submit();
+        });
+        </script>"##))
+    }
+}
+
+/// Renders the page that decrypts an encrypted paste in the browser, using the key in the fragment of the link.
+/// The decrypted text is only ever set as text content, so it is never interpreted as html.
+pub fn render_decryption_page(ciphertext: &str) -> String {
+    html! {
+        p id="decrypt-status" {"This paste is encrypted, decrypting it in your browser..."}
+        pre id="decrypted-paste" data-ciphertext=(ciphertext.trim()) {}
+        (PreEscaped(r#"<script>
+        (async () => {
+            const status = document.getElementById("decrypt-status");
+            const output = document.getElementById("decrypted-paste");
+            const keyText = window.location.hash.slice(1);
+            if (!keyText) {
+                status.textContent = "This paste is encrypted, and the link is missing the key after the #, so it can not be decrypted.";
+                return;
+            }
+            const fromBase64 = (text) => Uint8Array.from(atob(text.replace(/-/g, "+").replace(/_/g, "/")), (char) => char.charCodeAt(0));
+            try {
+                const data = fromBase64(output.dataset.ciphertext);
+                const key = await crypto.subtle.importKey("raw", fromBase64(keyText), "AES-GCM", false, ["decrypt"]);
+                const plaintext = await crypto.subtle.decrypt({ name: "AES-GCM", iv: data.slice(0, 12) }, key, data.slice(12));
+                output.textContent = new TextDecoder().decode(plaintext);
+                status.textContent = "Decrypted in your browser, the server never saw this text.";
+            } catch (err) {
+                status.textContent = "Unable to decrypt this paste, the key in the link is wrong.";
+            }
+        })();
+        </script>"#))
+    }
+    .into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paste_encryption::{is_valid_ciphertext, plaintext_length, render_decryption_page};
+
+    #[test]
+    fn encrypted_paste_contents() {
+        // 12 bytes of iv and 16 bytes of tag, the ciphertext of an empty paste.
+        assert!(is_valid_ciphertext(
+            "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGw=="
+        ));
+        assert!(!is_valid_ciphertext("AAECAwQFBgcICQoL"));
+        assert!(!is_valid_ciphertext("my secret password"));
+        assert_eq!(
+            plaintext_length("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGw=="),
+            Some(0)
+        );
+        assert_eq!(plaintext_length("AAECAwQFBgcICQoL"), None);
+        assert!(render_decryption_page("\"><script>")
+            .contains("data-ciphertext=\"&quot;&gt;&lt;script&gt;\""));
+    }
+}
@@ -0,0 +1,36 @@
+use rocket::http::MediaType;
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+
+/// User agents of command line clients, these are given plain text instead of a html page.
+pub static PLAIN_TEXT_USER_AGENTS: [&str; 3] = ["curl/", "wget/", "httpie/"];
+
+#[derive(Default)]
+/// Request guard that returns if the client would rather have plain text than html,
+/// either because it is a command line client, or because it prefers text/plain in its accept header.
+pub struct WantsPlainTextGuard(pub bool);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WantsPlainTextGuard {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let is_cli_client = req
+            .headers()
+            .get_one("User-Agent")
+            .map(|user_agent| {
+                let user_agent = user_agent.to_lowercase();
+                PLAIN_TEXT_USER_AGENTS
+                    .iter()
+                    .any(|client| user_agent.starts_with(client))
+            })
+            .unwrap_or(false);
+
+        let prefers_plain_text = req
+            .accept()
+            .map(|accept| accept.preferred().media_type() == &MediaType::Plain)
+            .unwrap_or(false);
+
+        Outcome::Success(Self(is_cli_client || prefers_plain_text))
+    }
+}
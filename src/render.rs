@@ -0,0 +1,279 @@
+use crate::highlight::render_code;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use maud::{html, PreEscaped};
+use pulldown_cmark::{html::push_html, Options, Parser};
+use std::path::Path;
+
+/// Images larger than this many bytes are not previewed inline, and are only offered as a download.
+pub static INLINE_IMAGE_SIZE_CAP: usize = 4 * 1024 * 1024;
+
+/// The number of bytes of a binary file shown in its hex dump preview.
+pub static HEX_DUMP_BYTES_CAP: usize = 4096;
+
+/// The maximum number of rows of a csv or tsv paste rendered as a table, the rest is left to the raw view.
+pub static TABLE_ROWS_CAP: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How the contents of a paste are shown on its page, chosen from its language, file name and contents.
+pub enum RenderMode {
+    Code,
+    Markdown,
+    Table(u8),           // the delimiter of the columns, a comma for csv or a tab for tsv
+    Image(&'static str), // the mime type of the image
+    Binary,
+}
+
+impl RenderMode {
+    /// Picks how to render the paste, in order of preference from the explicit language, the extension of the file name, and then the contents.
+    pub fn detect(language: Option<&str>, file_name: Option<&str>, bytes: &[u8]) -> Self {
+        let extension = file_name
+            .and_then(|file_name| Path::new(file_name).extension())
+            .and_then(|extension| extension.to_str());
+        let by_name = |name: &str| match name.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Some(RenderMode::Markdown),
+            "csv" => Some(RenderMode::Table(b',')),
+            "tsv" => Some(RenderMode::Table(b'\t')),
+            _ => None,
+        };
+
+        if let Some(mime) = sniff_image(bytes) {
+            return RenderMode::Image(mime);
+        }
+        if std::str::from_utf8(bytes).is_err() {
+            return RenderMode::Binary;
+        }
+        language
+            .and_then(by_name)
+            .or_else(|| extension.and_then(by_name))
+            .unwrap_or(RenderMode::Code)
+    }
+}
+
+/// Returns the mime type of the image if the bytes start with the signature of an image format browsers can show.
+/// Svg images are left out on purpose, as they can carry scripts.
+fn sniff_image(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if is_bmp(bytes) {
+        Some("image/bmp")
+    } else {
+        None
+    }
+}
+
+/// Returns true if the bytes have a valid bmp header, the file size it records and a known dib header size.
+/// Only checking the "BM" signature would show text pastes starting with those letters as a broken image.
+fn is_bmp(bytes: &[u8]) -> bool {
+    if !bytes.starts_with(b"BM") || bytes.len() < 18 {
+        return false;
+    }
+    let file_size = u32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]) as usize;
+    let dib_header_size = u32::from_le_bytes([bytes[14], bytes[15], bytes[16], bytes[17]]);
+    file_size == bytes.len() && matches!(dib_header_size, 12 | 40 | 52 | 56 | 64 | 108 | 124)
+}
+
+/// Renders the contents of a paste in the given mode, or as plain numbered lines when the raw view is asked for.
+/// The download link is offered for contents that can not be shown as text.
+pub fn render_paste(
+    bytes: &[u8],
+    mode: RenderMode,
+    raw: bool,
+    language: Option<&str>,
+    file_name: Option<&str>,
+    download_link: &str,
+) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    match mode {
+        RenderMode::Image(_) | RenderMode::Binary if raw => render_hex_dump(bytes, download_link),
+        _ if raw => render_code(&text, Some("Plain Text"), None),
+        RenderMode::Code => render_code(&text, language, file_name),
+        RenderMode::Markdown => render_markdown(&text),
+        RenderMode::Table(delimiter) => render_table(&text, delimiter),
+        RenderMode::Image(mime) => render_image(bytes, mime, download_link),
+        RenderMode::Binary => render_hex_dump(bytes, download_link),
+    }
+}
+
+/// Renders markdown to html, sanitized so that no scripts, styles or event handlers written in the markdown make it to the page.
+pub fn render_markdown(text: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+    let mut unsafe_html = String::new();
+    push_html(&mut unsafe_html, Parser::new_ext(text, options));
+
+    html! {
+        div."markdown" {(PreEscaped(ammonia::clean(&unsafe_html)))}
+    }
+    .into_string()
+}
+
+/// Renders csv or tsv as a table, with the first row as the header.
+/// Rows that can not be parsed are skipped, and only the first TABLE_ROWS_CAP rows are shown.
+pub fn render_table(text: &str, delimiter: u8) -> String {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let mut rows = reader
+        .records()
+        .filter_map(|record| record.ok())
+        .take(TABLE_ROWS_CAP + 1)
+        .map(|record| record.iter().map(|field| field.to_string()).collect())
+        .collect::<Vec<Vec<String>>>();
+    let is_truncated = rows.len() > TABLE_ROWS_CAP;
+    rows.truncate(TABLE_ROWS_CAP);
+    let header = if rows.is_empty() {
+        vec![]
+    } else {
+        rows.remove(0)
+    };
+
+    html! {
+        table."csv" {
+            tr {
+                @for field in &header {
+                    th {(field)}
+                }
+            }
+            @for row in &rows {
+                tr {
+                    @for field in row {
+                        td {(field)}
+                    }
+                }
+            }
+        }
+        @if is_truncated {
+            p {"Only the first " (TABLE_ROWS_CAP) " rows are shown, view raw to see the rest."}
+        }
+        (PreEscaped(r#"<style>
+        table.csv {
+            border-collapse: collapse;
+        }
+        table.csv th, table.csv td {
+            border: 1px solid lightgray;
+            padding: 2px 8px;
+        }</style>"#))
+    }
+    .into_string()
+}
+
+/// Renders the image inline as a data url, so showing it does not need a second request that would count as another view.
+pub fn render_image(bytes: &[u8], mime: &str, download_link: &str) -> String {
+    if bytes.len() > INLINE_IMAGE_SIZE_CAP {
+        return html! {
+            p {"This image is too large to preview."}
+            a href=(download_link) {"Download image"}
+        }
+        .into_string();
+    }
+    let data_url = format!("data:{};base64,{}", mime, STANDARD.encode(bytes));
+
+    html! {
+        img src=(data_url) alt="Pasted image" style="max-width: 100%;";
+    }
+    .into_string()
+}
+
+/// Renders the first HEX_DUMP_BYTES_CAP bytes as a hex dump, in the layout of `xxd`, with a link to download the whole file.
+pub fn render_hex_dump(bytes: &[u8], download_link: &str) -> String {
+    let lines = bytes
+        .chunks(16)
+        .take(HEX_DUMP_BYTES_CAP / 16)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let hex = chunk
+                .chunks(2)
+                .map(hex::encode)
+                .collect::<Vec<String>>()
+                .join(" ");
+            let ascii = chunk
+                .iter()
+                .map(|byte| match byte.is_ascii_graphic() || *byte == b' ' {
+                    true => *byte as char,
+                    false => '.',
+                })
+                .collect::<String>();
+            format!("{:08x}: {:<39}  {}", index * 16, hex, ascii)
+        })
+        .collect::<Vec<String>>();
+
+    html! {
+        p {"Binary file, " (bytes.len()) " bytes. " a href=(download_link) {"Download file"}}
+        pre {(lines.join("\n"))}
+        @if bytes.len() > HEX_DUMP_BYTES_CAP {
+            p {"Only the first " (HEX_DUMP_BYTES_CAP) " bytes are shown."}
+        }
+    }
+    .into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::render::{render_hex_dump, render_markdown, render_table, RenderMode};
+
+    #[test]
+    fn render_modes() {
+        assert_eq!(
+            RenderMode::detect(None, Some("README.md"), b"# hi"),
+            RenderMode::Markdown
+        );
+        assert_eq!(
+            RenderMode::detect(Some("csv"), None, b"a,b"),
+            RenderMode::Table(b',')
+        );
+        assert_eq!(
+            RenderMode::detect(None, Some("data.tsv"), b"a\tb"),
+            RenderMode::Table(b'\t')
+        );
+        assert_eq!(
+            RenderMode::detect(Some("markdown"), Some("cat.png"), b"\x89PNG\r\n\x1a\n"),
+            RenderMode::Image("image/png")
+        );
+        assert_eq!(
+            RenderMode::detect(None, None, &[0, 159, 146, 150]),
+            RenderMode::Binary
+        );
+        assert_eq!(
+            RenderMode::detect(None, None, b"BMW service notes, oil changed at 30k miles"),
+            RenderMode::Code
+        );
+        let mut bmp = b"BM".to_vec();
+        bmp.extend(26u32.to_le_bytes());
+        bmp.extend([0; 8]);
+        bmp.extend(12u32.to_le_bytes());
+        bmp.extend([0; 8]);
+        assert_eq!(
+            RenderMode::detect(None, None, &bmp),
+            RenderMode::Image("image/bmp")
+        );
+        assert_eq!(
+            RenderMode::detect(None, Some("main.rs"), b"fn main() {}"),
+            RenderMode::Code
+        );
+
+        let markdown =
+            render_markdown("# Title\n<script>alert(1)</script>\n[x](javascript:alert(1))");
+        assert!(markdown.contains("<h1>Title</h1>"));
+        assert!(!markdown.contains("<script>"));
+        assert!(!markdown.contains("javascript:"));
+
+        let table = render_table("name,note\nbob,\"<b>hi</b>, there\"", b',');
+        assert!(table.contains("<th>name</th>"));
+        assert!(table.contains("<td>&lt;b&gt;hi&lt;/b&gt;, there</td>"));
+
+        let dump = render_hex_dump(b"hello\x00", "/download");
+        assert!(dump.contains("00000000: 6865 6c6c 6f00"));
+        assert!(dump.contains("hello."));
+    }
+}
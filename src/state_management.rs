@@ -1,7 +1,16 @@
+use crate::api_token::ApiToken;
+use crate::chat::ChatHub;
+use crate::email::{DigestState, SmtpConfig};
+use crate::encryption::{MessageEncryption, UnlockedKey};
 use crate::metrics::UserMetric;
 use crate::paste::Paste;
+use crate::paste_access::PasswordAttempts;
+use crate::paste_cleanup::{CleanupConfig, CleanupReport};
+use crate::paste_counter::CounterConfig;
 use crate::user::User;
+use crate::webhook::{WebhookConfig, WebhookDelivery};
 use chrono::{DateTime, Datelike, Local, Timelike};
+use rocket::tokio::sync::Notify;
 use rocket::State;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -9,7 +18,7 @@ use std::fs;
 use std::fs::File;
 use std::io::{BufWriter, Read, Write};
 use std::path::PathBuf;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 #[derive(Serialize, Deserialize)]
 /// A serializable version of the TYRState struct, used only for saving.
@@ -21,6 +30,9 @@ pub struct StateSave {
     pub admin_state: Option<AdminState>,
     pub unique_users: Option<HashMap<String, UserMetric>>,
     pub pastes: Option<HashMap<String, Paste>>,
+    pub webhook_deliveries: Option<Vec<WebhookDelivery>>,
+    pub digest_state: Option<DigestState>,
+    pub api_tokens: Option<HashMap<String, ApiToken>>,
 }
 
 /// The state struct for the rocket web frame work.
@@ -34,6 +46,18 @@ pub struct TYRState {
     pub admin_state: Arc<RwLock<AdminState>>,
     pub unique_users: Arc<RwLock<HashMap<String, UserMetric>>>,
     pub pastes: Arc<RwLock<HashMap<String, Paste>>>,
+    pub webhook_deliveries: Arc<RwLock<Vec<WebhookDelivery>>>, // retry queue and history of webhooks sent to the host
+    pub webhook_config: Arc<RwLock<WebhookConfig>>,            // read from Rocket.toml at launch
+    pub webhook_notify: Arc<Notify>, // wakes the webhook worker when a new webhook is queued
+    pub smtp_config: Arc<RwLock<Option<SmtpConfig>>>, // read from Rocket.toml at launch, none if emails are disabled
+    pub digest_state: Arc<RwLock<DigestState>>,
+    pub chat: Arc<RwLock<ChatHub>>, // live chat sessions, transcripts are persisted as messages once a session closes
+    pub message_key: Arc<RwLock<HashMap<String, UnlockedKey>>>, // the hosts private key, keyed by the admin login that unlocked it
+    pub cleanup_config: Arc<RwLock<CleanupConfig>>,             // read from Rocket.toml at launch
+    pub cleanup_report: Arc<RwLock<Option<CleanupReport>>>, // report of the most recent paste cleanup
+    pub api_tokens: Arc<RwLock<HashMap<String, ApiToken>>>, // api tokens keyed by the hash of the token
+    pub counter_config: Arc<RwLock<CounterConfig>>,         // read from Rocket.toml at launch
+    pub password_attempts: Arc<RwLock<HashMap<String, PasswordAttempts>>>, // wrong paste passwords keyed by ip
 }
 
 impl TYRState {
@@ -46,6 +70,20 @@ impl TYRState {
             admin_state: Arc::new(RwLock::new(state_save.admin_state.unwrap_or_default())),
             unique_users: Arc::new(RwLock::new(state_save.unique_users.unwrap_or_default())),
             pastes: Arc::new(RwLock::new(state_save.pastes.unwrap_or_default())),
+            webhook_deliveries: Arc::new(RwLock::new(
+                state_save.webhook_deliveries.unwrap_or_default(),
+            )),
+            webhook_config: Arc::new(Default::default()),
+            webhook_notify: Arc::new(Notify::new()),
+            smtp_config: Arc::new(Default::default()),
+            digest_state: Arc::new(RwLock::new(state_save.digest_state.unwrap_or_default())),
+            chat: Arc::new(Default::default()),
+            message_key: Arc::new(Default::default()),
+            cleanup_config: Arc::new(Default::default()),
+            counter_config: Arc::new(Default::default()),
+            cleanup_report: Arc::new(Default::default()),
+            api_tokens: Arc::new(RwLock::new(state_save.api_tokens.unwrap_or_default())),
+            password_attempts: Arc::new(Default::default()),
         }
     }
 }
@@ -56,6 +94,7 @@ pub struct AdminState {
     pub admin_created: bool,
     pub admin_hashes: Vec<String>,
     pub verified_list: Option<Vec<String>>,
+    pub message_encryption: Option<MessageEncryption>, // if set, new messages are encrypted to the hosts public key
 }
 
 impl Default for TYRState {
@@ -67,6 +106,18 @@ impl Default for TYRState {
             admin_state: Arc::from(RwLock::from(AdminState::default())),
             unique_users: Arc::new(Default::default()),
             pastes: Arc::new(Default::default()),
+            webhook_deliveries: Arc::new(Default::default()),
+            webhook_config: Arc::new(Default::default()),
+            webhook_notify: Arc::new(Notify::new()),
+            smtp_config: Arc::new(Default::default()),
+            digest_state: Arc::new(Default::default()),
+            chat: Arc::new(Default::default()),
+            message_key: Arc::new(Default::default()),
+            cleanup_config: Arc::new(Default::default()),
+            counter_config: Arc::new(Default::default()),
+            cleanup_report: Arc::new(Default::default()),
+            api_tokens: Arc::new(Default::default()),
+            password_attempts: Arc::new(Default::default()),
         }
     }
 }
@@ -83,6 +134,9 @@ pub fn load_state_save(path: &PathBuf) -> StateSave {
                 admin_state: None,
                 unique_users: None,
                 pastes: None,
+                webhook_deliveries: None,
+                digest_state: None,
+                api_tokens: None,
             };
         }
     };
@@ -98,6 +152,9 @@ pub fn load_state_save(path: &PathBuf) -> StateSave {
                 admin_state: None,
                 unique_users: None,
                 pastes: None,
+                webhook_deliveries: None,
+                digest_state: None,
+                api_tokens: None,
             };
         }
     }
@@ -112,13 +169,24 @@ pub fn load_state_save(path: &PathBuf) -> StateSave {
                 admin_state: None,
                 unique_users: None,
                 pastes: None,
+                webhook_deliveries: None,
+                digest_state: None,
+                api_tokens: None,
             }
         }
     }
 }
 
+/// Held for the whole of a save, so that concurrent savers (handlers, the webhook worker, the digest
+/// and cleanup sweepers) never interleave their writes to the same files.
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
 /// Saves all messages to the system in a file.
+/// The state is written to a temporary file next to `path` and then renamed over it, so a crash mid
+/// save never leaves a truncated state behind.
 pub fn save_program_state(messages: &State<TYRState>, path: &PathBuf) {
+    let _save_guard = SAVE_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
     match fs::read_dir(path.parent().unwrap()) {
         Ok(_) => {
             // output dir exists
@@ -143,17 +211,34 @@ pub fn save_program_state(messages: &State<TYRState>, path: &PathBuf) {
             admin_state: Some(messages.admin_state.read().unwrap().clone()),
             unique_users: Some(messages.unique_users.read().unwrap().clone()),
             pastes: Some(messages.pastes.read().unwrap().clone()),
+            webhook_deliveries: Some(messages.webhook_deliveries.read().unwrap().clone()),
+            digest_state: Some(messages.digest_state.read().unwrap().clone()),
+            api_tokens: Some(messages.api_tokens.read().unwrap().clone()),
         };
 
         let ser = serde_json::to_string(&state_save).unwrap();
 
-        let mut ser_file = File::create(path).unwrap();
-
+        let temp_path = path.with_extension("ser.tmp");
+        let mut ser_file = File::create(&temp_path).unwrap();
         ser_file.write_all(ser.as_ref()).unwrap();
+        ser_file.sync_all().unwrap();
+        fs::rename(&temp_path, path).unwrap();
     }
 
     let file_name = { format!("{}/messages.sav", path.parent().unwrap().to_str().unwrap()) };
 
+    if messages
+        .admin_state
+        .read()
+        .unwrap()
+        .message_encryption
+        .is_some()
+    {
+        // messages are encrypted to the host, so never render them out in plain text, and remove any old render.
+        let _ = fs::remove_file(file_name);
+        return;
+    }
+
     // block for rendering out the user data into a pretty file for the host :)
     let file = File::create(file_name).unwrap();
     let mut bw = BufWriter::new(file);
@@ -191,6 +276,7 @@ pub fn save_program_state(messages: &State<TYRState>, path: &PathBuf) {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::message::Message;
     use std::time::SystemTime;
 
     #[test]
@@ -201,6 +287,18 @@ mod test {
             admin_state: Arc::new(Default::default()),
             unique_users: Arc::new(Default::default()),
             pastes: Arc::new(Default::default()),
+            webhook_deliveries: Arc::new(Default::default()),
+            webhook_config: Arc::new(Default::default()),
+            webhook_notify: Arc::new(Notify::new()),
+            smtp_config: Arc::new(Default::default()),
+            digest_state: Arc::new(Default::default()),
+            chat: Arc::new(Default::default()),
+            message_key: Arc::new(Default::default()),
+            cleanup_config: Arc::new(Default::default()),
+            counter_config: Arc::new(Default::default()),
+            cleanup_report: Arc::new(Default::default()),
+            api_tokens: Arc::new(Default::default()),
+            password_attempts: Arc::new(Default::default()),
         };
         state.admin_state.write().unwrap().admin_created = true;
         state
@@ -252,7 +350,7 @@ mod test {
             .unwrap()
             .get_mut("4.1.2.3")
             .unwrap()
-            .push("lmao".to_string(), None);
+            .push(Message::new("lmao".to_string(), None, None, None).unwrap());
         let rocket = rocket::build().manage(state.clone());
         save_program_state(
             State::get(&rocket).unwrap(),
@@ -303,4 +401,31 @@ mod test {
 
         fs::remove_dir_all(PathBuf::from("./test")).unwrap();
     }
+
+    #[test]
+    fn concurrent_saves_leave_a_loadable_state() {
+        let state = TYRState::default();
+        state
+            .banned_ips
+            .write()
+            .unwrap()
+            .push("1.2.3.4".to_string());
+        let rocket = rocket::build().manage(state.clone());
+        let path = PathBuf::from("./test_concurrent_save/state.ser");
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| save_program_state(State::get(&rocket).unwrap(), &path));
+            }
+        });
+
+        let loaded_state = TYRState::from_state_save(load_state_save(&path));
+        assert_eq!(
+            state.banned_ips.read().unwrap().clone(),
+            loaded_state.banned_ips.read().unwrap().clone()
+        );
+        assert!(!path.with_extension("ser.tmp").exists());
+
+        fs::remove_dir_all(PathBuf::from("./test_concurrent_save")).unwrap();
+    }
 }
@@ -1,8 +1,6 @@
 use crate::message::{Message, NewMessage};
 use crate::POST_COOLDOWN;
-use chrono::Utc;
 use rocket::form::Form;
-use rocket::http::Cookie;
 use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
 
@@ -32,14 +30,7 @@ impl User {
         }
     }
     /// Add a new message to a user, and update their last time of posting
-    pub(crate) fn push(&mut self, msg: String, hash: Option<&Cookie>) {
-        let time = Utc::now();
-
-        let message: Message = Message {
-            text: msg,
-            time_stamp: time,
-            user_hash: { hash.map(|cookie| cookie.value().to_string()) },
-        };
+    pub(crate) fn push(&mut self, message: Message) {
         self.messages.push(message);
         self.last_time_post = SystemTime::now();
     }
@@ -56,7 +47,6 @@ impl User {
     pub(crate) fn is_dupe_message(&self, msg: &Form<NewMessage>) -> bool {
         self.messages
             .iter()
-            .map(|msg| &msg.text)
-            .any(|x| x == &msg.msg)
+            .any(|message| message.has_text(&msg.msg))
     }
 }
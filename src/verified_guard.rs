@@ -1,4 +1,5 @@
 use crate::state_management::TYRState;
+use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome};
 use rocket::{Request, State};
 
@@ -44,7 +45,7 @@ impl<'r> FromRequest<'r> for RequireVerifiedGuard {
         let outcome: &State<TYRState> = req.guard::<&State<TYRState>>().await.unwrap();
 
         return match &outcome.admin_state.read().unwrap().verified_list {
-            None => Outcome::Forward(()), // if no verified list exists, then clearly this user is not verified.
+            None => Outcome::Forward(Status::Forbidden), // if no verified list exists, then clearly this user is not verified.
 
             Some(ver_list) => {
                 // if the user is logged in
@@ -57,7 +58,7 @@ impl<'r> FromRequest<'r> for RequireVerifiedGuard {
                 if ver_list.contains(&user_ip) {
                     return Outcome::Success(Self(true));
                 }
-                Outcome::Forward(())
+                Outcome::Forward(Status::Forbidden)
             }
         };
     }
@@ -0,0 +1,336 @@
+use crate::state_management::{save_program_state, TYRState};
+use chrono::{DateTime, Duration, Local, Utc};
+use hmac::{Hmac, Mac};
+use rocket::tokio::select;
+use rocket::tokio::time::sleep;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::PathBuf;
+
+/// The number of finished webhook deliveries kept for the admin history page.
+pub static WEBHOOK_HISTORY_CAP: usize = 100;
+
+/// The number of attempts made to deliver a webhook before it is given up on.
+pub static WEBHOOK_MAX_ATTEMPTS: u32 = 8;
+
+/// The delay in seconds before the first retry of a failed delivery, doubled after each further failure.
+pub static WEBHOOK_BASE_BACKOFF: i64 = 30;
+
+/// The duration in seconds the webhook worker waits between checks of the retry queue.
+pub static WEBHOOK_WORKER_INTERVAL: u64 = 10;
+
+/// The duration in seconds a webhook receiver has to respond before the delivery counts as failed.
+pub static WEBHOOK_TIMEOUT: u64 = 10;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+/// Webhook settings read from the "webhooks" table of Rocket.toml, no webhooks are sent if no urls are given.
+pub struct WebhookConfig {
+    #[serde(default)]
+    pub urls: Vec<String>,
+    /// The secret used to sign every payload, receivers compare it against the "X-TYR-Signature" header.
+    /// Required when urls are given, webhooks are disabled without one.
+    #[serde(default)]
+    pub secret: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+/// An event the host is notified about, serialized into the json payload of a webhook.
+pub enum WebhookEvent {
+    NewMessage {
+        ip: String,
+        text: Option<String>, // none if the message is encrypted to the hosts key
+        logged_in: bool,
+    },
+    NewPaste {
+        paste_id: String,
+        ip: String,
+    },
+    Upload {
+        paste_id: String,
+        ip: String,
+        file_name: String,
+    },
+    Ban {
+        ip: String,
+    },
+}
+
+impl WebhookEvent {
+    /// Returns the name of the event, matching the "event" field of the payload.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::NewMessage { .. } => "new_message",
+            WebhookEvent::NewPaste { .. } => "new_paste",
+            WebhookEvent::Upload { .. } => "upload",
+            WebhookEvent::Ban { .. } => "ban",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+/// The json body sent to a webhook receiver.
+struct WebhookPayload<'a> {
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+    delivery_id: &'a str,
+    time: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+/// A single webhook sent to a single url, kept in the retry queue until it is delivered or given up on.
+pub struct WebhookDelivery {
+    pub id: String,
+    pub url: String,
+    pub event_name: String,
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub created: DateTime<Local>,
+    pub next_attempt: DateTime<Local>,
+    pub last_response: Option<String>,
+}
+
+impl WebhookDelivery {
+    /// Records the outcome of a delivery attempt, scheduling a retry with exponential backoff if it failed.
+    fn record_attempt(&mut self, outcome: Result<String, String>) {
+        self.attempts += 1;
+        match outcome {
+            Ok(response) => {
+                self.status = DeliveryStatus::Delivered;
+                self.last_response = Some(response);
+            }
+            Err(response) => {
+                self.last_response = Some(response);
+                if self.attempts >= WEBHOOK_MAX_ATTEMPTS {
+                    self.status = DeliveryStatus::Failed;
+                } else {
+                    let backoff = WEBHOOK_BASE_BACKOFF * 2_i64.pow(self.attempts - 1);
+                    self.next_attempt = Local::now() + Duration::seconds(backoff);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the hex encoded HMAC-SHA256 of the payload, keyed with the webhook secret.
+pub fn sign_payload(secret: &str, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Queues the event for delivery to every configured webhook url, and wakes the webhook worker.
+pub fn queue_webhook(state: &TYRState, event: WebhookEvent) {
+    let urls = state.webhook_config.read().unwrap().urls.clone();
+    if urls.is_empty() {
+        return;
+    }
+
+    {
+        let mut lock = state.webhook_deliveries.write().unwrap();
+        for url in urls {
+            let id = uuid::Uuid::new_v4().to_string();
+            let payload = serde_json::to_string(&WebhookPayload {
+                event: &event,
+                delivery_id: &id,
+                time: Utc::now(),
+            })
+            .unwrap();
+            lock.push(WebhookDelivery {
+                id,
+                url,
+                event_name: event.name().to_string(),
+                payload,
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                created: Local::now(),
+                next_attempt: Local::now(),
+                last_response: None,
+            });
+        }
+    }
+
+    state.webhook_notify.notify_one();
+}
+
+/// Sends a single delivery, returning the response status if the receiver accepted it, or a description of the error otherwise.
+async fn send_delivery(
+    client: &reqwest::Client,
+    delivery: &WebhookDelivery,
+    secret: &str,
+) -> Result<String, String> {
+    let response = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("X-TYR-Event", &delivery.event_name)
+        .header("X-TYR-Delivery", &delivery.id)
+        .header(
+            "X-TYR-Signature",
+            format!("sha256={}", sign_payload(secret, &delivery.payload)),
+        )
+        .timeout(std::time::Duration::from_secs(WEBHOOK_TIMEOUT))
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    if response.status().is_success() {
+        Ok(response.status().to_string())
+    } else {
+        Err(response.status().to_string())
+    }
+}
+
+/// Attempts every pending delivery that is due, returns true if any delivery was attempted.
+async fn deliver_due(state: &TYRState, client: &reqwest::Client) -> bool {
+    let secret = state.webhook_config.read().unwrap().secret.clone();
+    let due = {
+        state
+            .webhook_deliveries
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|delivery| delivery.status == DeliveryStatus::Pending)
+            .filter(|delivery| delivery.next_attempt <= Local::now())
+            .cloned()
+            .collect::<Vec<WebhookDelivery>>()
+    }; // clone the due deliveries so no lock is held while waiting on the network.
+
+    if due.is_empty() {
+        return false;
+    }
+
+    for delivery in due {
+        let outcome = send_delivery(client, &delivery, &secret).await;
+        let mut lock = state.webhook_deliveries.write().unwrap();
+        if let Some(queued) = lock.iter_mut().find(|queued| queued.id == delivery.id) {
+            queued.record_attempt(outcome);
+        }
+    }
+
+    trim_delivery_history(&mut state.webhook_deliveries.write().unwrap());
+    true
+}
+
+/// Removes the oldest finished deliveries past the history cap, pending deliveries are always kept.
+fn trim_delivery_history(deliveries: &mut Vec<WebhookDelivery>) {
+    let finished_count = deliveries
+        .iter()
+        .filter(|delivery| delivery.status != DeliveryStatus::Pending)
+        .count();
+    let mut to_remove = finished_count.saturating_sub(WEBHOOK_HISTORY_CAP);
+    deliveries.retain(|delivery| {
+        if to_remove > 0 && delivery.status != DeliveryStatus::Pending {
+            to_remove -= 1;
+            false
+        } else {
+            true
+        }
+    });
+}
+
+/// Background task that delivers queued webhooks when woken, and retries failed ones periodically.
+/// The retry queue is saved with the rest of the program state after each round of deliveries.
+pub async fn webhook_worker(state: TYRState) {
+    let client = reqwest::Client::new();
+    loop {
+        select! {
+            _ = state.webhook_notify.notified() => {}
+            _ = sleep(std::time::Duration::from_secs(WEBHOOK_WORKER_INTERVAL)) => {}
+        }
+
+        if deliver_due(&state, &client).await {
+            save_program_state((&state).into(), &PathBuf::from("./output/state.ser"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use rocket::tokio::net::TcpListener;
+
+    #[test]
+    fn test_sign_payload() {
+        // test case 2 of RFC 4231
+        assert_eq!(
+            sign_payload("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_record_attempt_backoff() {
+        let mut delivery = WebhookDelivery {
+            id: "id".to_string(),
+            url: "http://127.0.0.1:1/".to_string(),
+            event_name: "ban".to_string(),
+            payload: "{}".to_string(),
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            created: Local::now(),
+            next_attempt: Local::now(),
+            last_response: None,
+        };
+
+        delivery.record_attempt(Err("500".to_string()));
+        assert_eq!(delivery.status, DeliveryStatus::Pending);
+        assert!(delivery.next_attempt > Local::now() + Duration::seconds(WEBHOOK_BASE_BACKOFF - 5));
+
+        for _ in 1..WEBHOOK_MAX_ATTEMPTS {
+            delivery.record_attempt(Err("500".to_string()));
+        }
+        assert_eq!(delivery.status, DeliveryStatus::Failed);
+    }
+
+    #[rocket::async_test]
+    async fn test_delivery_to_local_receiver() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        // a stand in webhook receiver that accepts a single request and returns it as text.
+        let receiver = rocket::tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let read = socket.read(&mut request).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&request[..read]).to_string()
+        });
+
+        let state = TYRState::default();
+        *state.webhook_config.write().unwrap() = WebhookConfig {
+            urls: vec![format!("http://{address}/hook")],
+            secret: "test secret".to_string(),
+        };
+        queue_webhook(
+            &state,
+            WebhookEvent::Ban {
+                ip: "1.2.3.4".to_string(),
+            },
+        );
+
+        assert!(deliver_due(&state, &reqwest::Client::new()).await);
+
+        let request = receiver.await.unwrap().to_lowercase();
+        let delivery = state.webhook_deliveries.read().unwrap()[0].clone();
+        assert_eq!(delivery.status, DeliveryStatus::Delivered);
+        assert!(delivery.payload.contains("\"event\":\"ban\""));
+        assert!(request.contains(&format!(
+            "x-tyr-signature: sha256={}",
+            sign_payload("test secret", &delivery.payload)
+        )));
+    }
+}